@@ -11,3 +11,43 @@ BINARY_NAME=$(cargo metadata --no-deps --format-version 1 | jq -r '.packages[0].
 sudo mv "target/release/$BINARY_NAME" /usr/local/bin/
 
 echo "Installed $BINARY_NAME to /usr/local/bin"
+
+# Generate systemd units: the socket unit owns both sockets so clients can
+# connect while the daemon restarts
+UNIT_DIR=/etc/systemd/system
+RUN_DIR=/run/fw-fanctrl-rs
+
+sudo tee "$UNIT_DIR/$BINARY_NAME.socket" > /dev/null <<UNIT
+[Unit]
+Description=Framework fan control sockets
+
+[Socket]
+ListenStream=$RUN_DIR/fw-fanctrl-rs.sock
+ListenStream=$RUN_DIR/fw-fanctrl-info.sock
+SocketMode=0666
+
+[Install]
+WantedBy=sockets.target
+UNIT
+
+sudo tee "$UNIT_DIR/$BINARY_NAME.service" > /dev/null <<UNIT
+[Unit]
+Description=Framework fan control daemon
+Requires=$BINARY_NAME.socket
+After=$BINARY_NAME.socket
+
+[Service]
+Type=notify
+ExecStart=/usr/local/bin/$BINARY_NAME run
+ExecReload=/bin/kill -HUP \$MAINPID
+WatchdogSec=30
+Restart=on-failure
+RestartSec=2
+
+[Install]
+WantedBy=multi-user.target
+UNIT
+
+sudo systemctl daemon-reload
+echo "Installed $BINARY_NAME.service and $BINARY_NAME.socket to $UNIT_DIR"
+echo "Enable with: sudo systemctl enable --now $BINARY_NAME.socket $BINARY_NAME.service"
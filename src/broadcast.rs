@@ -0,0 +1,234 @@
+use std::collections::VecDeque;
+use std::io::Write;
+use std::net::Shutdown;
+use std::os::unix::net::UnixStream;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+use log::{debug, warn};
+
+use crate::fan_config::{Overflow, SubscriberQueue};
+
+/// Most info subscribers served at once; each one costs a writer thread.
+pub const MAX_SUBSCRIBERS: usize = 16;
+
+#[derive(Default)]
+struct Queue {
+    messages: VecDeque<Arc<str>>,
+    closed: bool,
+    dropped: u64,
+}
+
+/// One subscriber's queue, drained by its own writer thread so a reader
+/// that stops reading only ever blocks that thread.
+struct Subscriber {
+    queue: Mutex<Queue>,
+    ready: Condvar,
+    stream: UnixStream,
+}
+
+impl Subscriber {
+    fn close(&self, queue: &mut Queue) {
+        queue.closed = true;
+        // unblocks a writer stuck in write_all
+        let _ = self.stream.shutdown(Shutdown::Both);
+        self.ready.notify_one();
+    }
+
+    fn write_loop(&self, mut stream: UnixStream) {
+        loop {
+            let msg = {
+                let mut queue = self.queue.lock().unwrap();
+                while queue.messages.is_empty() && !queue.closed {
+                    queue = self.ready.wait(queue).unwrap();
+                }
+                if queue.closed {
+                    return;
+                }
+                queue.messages.pop_front().unwrap()
+            };
+
+            if let Err(e) = stream.write_all(msg.as_bytes()) {
+                debug!("Info client disconnected: {}", e);
+                self.queue.lock().unwrap().closed = true;
+                return;
+            }
+        }
+    }
+}
+
+/// Fans events out to the info socket subscribers without ever waiting on
+/// one of them.
+pub struct Broadcaster {
+    subscribers: Mutex<Vec<Arc<Subscriber>>>,
+    capacity: usize,
+    overflow: Overflow,
+}
+
+impl Broadcaster {
+    pub fn new(config: &SubscriberQueue) -> Self {
+        Self {
+            subscribers: Mutex::new(Vec::new()),
+            capacity: config.capacity.max(1),
+            overflow: config.overflow,
+        }
+    }
+
+    /// Adds a subscriber whose first message is `snapshot()`. No message is
+    /// published between taking the snapshot and the subscriber joining.
+    /// Fails once `MAX_SUBSCRIBERS` are connected.
+    pub fn subscribe(
+        &self,
+        stream: UnixStream,
+        snapshot: impl FnOnce() -> String,
+    ) -> std::io::Result<()> {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain(|subscriber| !subscriber.queue.lock().unwrap().closed);
+        if subscribers.len() >= MAX_SUBSCRIBERS {
+            return Err(std::io::Error::other("too many info clients"));
+        }
+
+        let writer = stream.try_clone()?;
+        let mut queue = Queue::default();
+        queue.messages.push_back(snapshot().into());
+
+        let subscriber = Arc::new(Subscriber {
+            queue: Mutex::new(queue),
+            ready: Condvar::new(),
+            stream,
+        });
+        let thread_subscriber = Arc::clone(&subscriber);
+        thread::spawn(move || thread_subscriber.write_loop(writer));
+        subscribers.push(subscriber);
+        Ok(())
+    }
+
+    /// Queues `msg` for every subscriber, applying the overflow policy to
+    /// the ones that fell behind.
+    pub fn publish(&self, msg: &str) {
+        let msg: Arc<str> = msg.into();
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain(|subscriber| {
+            let mut queue = subscriber.queue.lock().unwrap();
+            if queue.closed {
+                return false;
+            }
+
+            if queue.messages.len() >= self.capacity {
+                match self.overflow {
+                    Overflow::DropOldest => {
+                        queue.messages.pop_front();
+                        queue.dropped += 1;
+                        if queue.dropped == 1 {
+                            warn!("Info client is not keeping up, dropping old events");
+                        }
+                    }
+                    Overflow::Disconnect => {
+                        warn!("Info client is not keeping up, disconnecting it");
+                        subscriber.close(&mut queue);
+                        return false;
+                    }
+                }
+            }
+
+            queue.messages.push_back(Arc::clone(&msg));
+            subscriber.ready.notify_one();
+            true
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::time::{Duration, Instant};
+
+    /// Large enough that a few hundred fill any socket buffer.
+    fn message(i: usize) -> String {
+        format!("{} {}\n", i, "x".repeat(8192))
+    }
+
+    fn read_all(stream: UnixStream) -> Vec<usize> {
+        BufReader::new(stream)
+            .lines()
+            .map_while(Result::ok)
+            .map(|line| line.split(' ').next().unwrap().parse().unwrap())
+            .collect()
+    }
+
+    /// Publishes at a pace any reader that actually reads keeps up with.
+    fn publish_all(broadcaster: &Broadcaster, count: usize) {
+        let start = Instant::now();
+        for i in 1..=count {
+            broadcaster.publish(&message(i));
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(start.elapsed() < Duration::from_secs(count as u64 / 100 + 2));
+    }
+
+    #[test]
+    fn slow_reader_skips_ahead_without_stalling_others() {
+        let broadcaster = Broadcaster::new(&SubscriberQueue {
+            capacity: 16,
+            overflow: Overflow::DropOldest,
+        });
+        let (fast, fast_reader) = UnixStream::pair().unwrap();
+        let (slow, slow_reader) = UnixStream::pair().unwrap();
+        broadcaster.subscribe(fast, || message(0)).unwrap();
+        broadcaster.subscribe(slow, || message(0)).unwrap();
+        let fast_reader = thread::spawn(move || read_all(fast_reader));
+
+        publish_all(&broadcaster, 300);
+        // let the fast reader catch up before hanging up on everyone
+        thread::sleep(Duration::from_millis(200));
+        let slow_reader = thread::spawn(move || read_all(slow_reader));
+        thread::sleep(Duration::from_millis(200));
+        for subscriber in broadcaster.subscribers.lock().unwrap().iter() {
+            subscriber.close(&mut subscriber.queue.lock().unwrap());
+        }
+
+        assert_eq!(fast_reader.join().unwrap(), (0..=300).collect::<Vec<_>>());
+        let slow = slow_reader.join().unwrap();
+        assert!(slow.len() < 300);
+        assert_eq!(slow.last(), Some(&300));
+        assert!(slow.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn subscribers_are_capped() {
+        let broadcaster = Broadcaster::new(&SubscriberQueue {
+            capacity: 16,
+            overflow: Overflow::DropOldest,
+        });
+        let mut readers = Vec::new();
+        for _ in 0..MAX_SUBSCRIBERS {
+            let (stream, reader) = UnixStream::pair().unwrap();
+            broadcaster.subscribe(stream, || message(0)).unwrap();
+            readers.push(reader);
+        }
+        let (stream, _reader) = UnixStream::pair().unwrap();
+        assert!(broadcaster.subscribe(stream, || message(0)).is_err());
+
+        // a disconnected subscriber frees its place
+        let first = Arc::clone(&broadcaster.subscribers.lock().unwrap()[0]);
+        first.close(&mut first.queue.lock().unwrap());
+        let (stream, _reader) = UnixStream::pair().unwrap();
+        broadcaster.subscribe(stream, || message(0)).unwrap();
+    }
+
+    #[test]
+    fn slow_reader_is_disconnected() {
+        let broadcaster = Broadcaster::new(&SubscriberQueue {
+            capacity: 16,
+            overflow: Overflow::Disconnect,
+        });
+        let (slow, slow_reader) = UnixStream::pair().unwrap();
+        broadcaster.subscribe(slow, || message(0)).unwrap();
+
+        publish_all(&broadcaster, 300);
+
+        assert!(broadcaster.subscribers.lock().unwrap().is_empty());
+        assert!(read_all(slow_reader).len() < 300);
+    }
+}
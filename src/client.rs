@@ -0,0 +1,279 @@
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::paths::Paths;
+use crate::protocol::{
+    Method, Outcome, Request, Response, RpcError, StrategyChanged, ToolOutput, PROTOCOL_VERSION,
+};
+use crate::{Event, Status};
+
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    /// The daemon understood the request and refused it.
+    Rpc(RpcError),
+    /// The daemon sent something that isn't a valid reply.
+    Protocol(String),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Rpc(e) => write!(f, "{}", e),
+            Error::Protocol(msg) => write!(f, "protocol error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Protocol(e.to_string())
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// A connection to the daemon's control socket. Requests are sent one at a
+/// time over the JSON protocol after a version handshake. The daemon hangs
+/// up on connections that stay idle for a minute, so long-running tools
+/// should connect again rather than keep one `Client` around.
+pub struct Client {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+    next_id: u64,
+    info_socket: PathBuf,
+}
+
+impl Client {
+    /// Connects to the sockets named by the `FW_FANCTRL_*` variables, or
+    /// the defaults.
+    pub fn connect_default() -> Result<Self> {
+        Self::connect(&Paths::from_env())
+    }
+
+    pub fn connect(paths: &Paths) -> Result<Self> {
+        let stream = UnixStream::connect(&paths.socket)?;
+        let mut client = Self {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            next_id: 0,
+            info_socket: paths.info_socket.clone(),
+        };
+        client.call(&Method::Hello {
+            version: PROTOCOL_VERSION,
+        })?;
+        Ok(client)
+    }
+
+    /// Sends any request and returns its raw result.
+    pub fn call(&mut self, method: &Method) -> Result<Value> {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let mut msg = serde_json::to_vec(&Request {
+            id: Some(id),
+            method: method.clone(),
+        })?;
+        msg.push(b'\n');
+        self.writer.write_all(&msg)?;
+
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(Error::Io(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "daemon closed the connection",
+            )));
+        }
+        let response: Response = serde_json::from_str(&line)?;
+        if response.id != Some(id) {
+            return Err(Error::Protocol(format!(
+                "expected reply to request {}, got {:?}",
+                id, response.id
+            )));
+        }
+        match response.outcome {
+            Outcome::Result(value) => Ok(value),
+            Outcome::Error(e) => Err(Error::Rpc(e)),
+        }
+    }
+
+    fn call_typed<T: DeserializeOwned>(&mut self, method: &Method) -> Result<T> {
+        Ok(serde_json::from_value(self.call(method)?)?)
+    }
+
+    pub fn use_strategy(&mut self, name: &str) -> Result<()> {
+        self.call(&Method::Use {
+            strategy: name.to_string(),
+        })?;
+        Ok(())
+    }
+
+    pub fn status(&mut self) -> Result<Status> {
+        self.call_typed(&Method::Status)
+    }
+
+    /// Switches back to the default strategy and returns its name.
+    pub fn reset(&mut self) -> Result<String> {
+        let changed: StrategyChanged = self.call_typed(&Method::Reset)?;
+        Ok(changed.strategy)
+    }
+
+    pub fn pause(&mut self) -> Result<()> {
+        self.call(&Method::Pause)?;
+        Ok(())
+    }
+
+    pub fn resume(&mut self) -> Result<()> {
+        self.call(&Method::Resume)?;
+        Ok(())
+    }
+
+    pub fn reload(&mut self) -> Result<()> {
+        self.call(&Method::Reload)?;
+        Ok(())
+    }
+
+    pub fn tool(&mut self, args: &[&str]) -> Result<ToolOutput> {
+        self.call_typed(&Method::Tool {
+            args: args.iter().map(|a| a.to_string()).collect(),
+        })
+    }
+
+    /// Streams events from the info socket that belongs to this daemon.
+    pub fn subscribe(&self) -> Result<Subscription> {
+        subscribe(&self.info_socket)
+    }
+}
+
+/// Streams events from `info_socket` until the daemon goes away.
+pub fn subscribe(info_socket: impl AsRef<Path>) -> Result<Subscription> {
+    let stream = UnixStream::connect(info_socket)?;
+    Ok(Subscription {
+        reader: BufReader::new(stream),
+    })
+}
+
+pub struct Subscription {
+    reader: BufReader<UnixStream>,
+}
+
+impl Iterator for Subscription {
+    type Item = Event;
+
+    /// Ends when the connection closes. Events this version doesn't know
+    /// are skipped.
+    fn next(&mut self) -> Option<Event> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            match self.reader.read_line(&mut line) {
+                Ok(0) | Err(_) => return None,
+                Ok(_) => {
+                    if let Ok(event) = serde_json::from_str(&line) {
+                        return Some(event);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{parse_request, ErrorCode};
+    use std::os::unix::net::UnixListener;
+    use std::thread;
+
+    fn temp_paths(dir: &tempfile::TempDir) -> Paths {
+        Paths {
+            socket: dir.path().join("control.sock"),
+            info_socket: dir.path().join("info.sock"),
+            config: PathBuf::new(),
+        }
+    }
+
+    /// Answers every request on a single connection with `reply`.
+    fn fake_daemon(paths: &Paths, reply: fn(&Method) -> Outcome) {
+        let listener = UnixListener::bind(&paths.socket).unwrap();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            for line in BufReader::new(stream).lines() {
+                let request = parse_request(&line.unwrap()).unwrap();
+                let response = Response {
+                    id: request.id,
+                    outcome: reply(&request.method),
+                };
+                writeln!(writer, "{}", serde_json::to_string(&response).unwrap()).unwrap();
+            }
+        });
+    }
+
+    #[test]
+    fn typed_calls_and_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = temp_paths(&dir);
+        fake_daemon(&paths, |method| match method {
+            Method::Hello { .. } => {
+                Outcome::Result(serde_json::json!({"version": 1, "daemon": "test"}))
+            }
+            Method::Status => Outcome::Result(serde_json::json!({
+                "strategy": "lazy", "speed": 30, "paused": false, "degraded": false,
+                "fans": [{"index": 0, "target": 30, "rpm": 2000}],
+            })),
+            _ => Outcome::Error(RpcError::new(
+                ErrorCode::UnknownStrategy,
+                "Unknown strategy: x",
+            )),
+        });
+
+        let mut client = Client::connect(&paths).unwrap();
+        let status = client.status().unwrap();
+        assert_eq!(status.strategy, "lazy");
+        assert_eq!(status.fans[0].rpm, Some(2000));
+
+        match client.use_strategy("x") {
+            Err(Error::Rpc(e)) => assert_eq!(e.code, ErrorCode::UnknownStrategy),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn subscription_skips_unknown_events() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = temp_paths(&dir);
+        let listener = UnixListener::bind(&paths.info_socket).unwrap();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream
+                .write_all(
+                    b"{\"event\":\"from_the_future\"}\n\
+                      {\"event\":\"power_source\",\"on_battery\":true,\"strategy\":\"lazy\"}\n",
+                )
+                .unwrap();
+        });
+
+        let events: Vec<Event> = subscribe(&paths.info_socket).unwrap().collect();
+        assert_eq!(
+            events,
+            vec![Event::PowerSource {
+                on_battery: true,
+                strategy: "lazy".to_string()
+            }]
+        );
+    }
+}
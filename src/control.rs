@@ -0,0 +1,226 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::thread::Thread;
+use std::time::Duration;
+
+use log::{error, info, warn};
+use serde::Serialize;
+
+use crate::fan_actuator::FanActuator;
+use crate::fan_config::{FanConfig, Strategy};
+use crate::temp_source::TemperatureSource;
+use crate::{reload_config, switch_strategy, Telemetry};
+use fw_fanctrl::protocol::{
+    parse_request, ErrorCode, Hello, Method, Outcome, Response, RpcError, StrategyChanged,
+    TextCommand, ToolOutput, PROTOCOL_VERSION,
+};
+use fw_fanctrl::{FanStatus, Status};
+
+/// Longest request line accepted, so a client can't grow the buffer forever.
+const MAX_REQUEST_LEN: u64 = 1 << 20;
+
+/// A client that sends or reads nothing for this long is dropped, so it
+/// can't hold its thread forever.
+const CLIENT_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Result of any control request, serialized as the `result` field.
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum Reply {
+    Hello(Hello),
+    StrategyChanged(StrategyChanged),
+    Status(Box<Status>),
+    Tool(ToolOutput),
+    /// Serialized as `null`.
+    Done,
+}
+
+/// The daemon state the control socket reads and changes, shared with the
+/// fan thread.
+#[derive(Clone)]
+pub struct Control {
+    pub config_path: PathBuf,
+    pub config: Arc<Mutex<FanConfig>>,
+    pub strategy_name: Arc<Mutex<String>>,
+    pub current_strategy: Arc<Mutex<Strategy>>,
+    pub paused: Arc<Mutex<bool>>,
+    pub fan_speed: Arc<Mutex<u8>>,
+    pub fans: Arc<Mutex<Vec<FanStatus>>>,
+    pub telemetry: Arc<Mutex<Telemetry>>,
+    pub degraded: Arc<Mutex<bool>>,
+    pub temp_source: Arc<Mutex<Box<dyn TemperatureSource>>>,
+    pub actuator: Arc<Mutex<Box<dyn FanActuator>>>,
+    pub fan_thread: Thread,
+}
+
+impl Control {
+    pub fn status(&self) -> Status {
+        let name_lock = self.strategy_name.lock().unwrap();
+        let fan_speed = self.fan_speed.lock().unwrap();
+        let paused = self.paused.lock().unwrap();
+
+        let fans = self.fans.lock().unwrap().clone();
+        let degraded = *self.degraded.lock().unwrap();
+
+        // same lock order as the fan thread: telemetry last
+        self.telemetry
+            .lock()
+            .unwrap()
+            .status(&name_lock, *fan_speed, *paused, fans, degraded)
+    }
+
+    fn use_strategy(&self, name: &str) -> Result<Reply, RpcError> {
+        let config = self.config.lock().unwrap();
+        if !switch_strategy(&config, name, &self.strategy_name, &self.current_strategy) {
+            warn!("Unknown strategy: {}", name);
+            return Err(RpcError::new(
+                ErrorCode::UnknownStrategy,
+                format!("Unknown strategy: {}", name),
+            ));
+        }
+        info!("Switched to strategy: {}", name);
+        Ok(Reply::StrategyChanged(StrategyChanged {
+            strategy: name.to_string(),
+        }))
+    }
+
+    pub fn handle(&self, method: &Method) -> Result<Reply, RpcError> {
+        match method {
+            Method::Hello { version } => {
+                if *version == 0 || *version > PROTOCOL_VERSION {
+                    return Err(RpcError::new(
+                        ErrorCode::UnsupportedVersion,
+                        format!(
+                            "Protocol version {} not supported, daemon speaks {}",
+                            version, PROTOCOL_VERSION
+                        ),
+                    ));
+                }
+                Ok(Reply::Hello(Hello {
+                    version: PROTOCOL_VERSION,
+                    daemon: env!("CARGO_PKG_VERSION").to_string(),
+                }))
+            }
+            Method::Use { strategy } => self.use_strategy(strategy),
+            Method::Status => Ok(Reply::Status(Box::new(self.status()))),
+            Method::Reset => {
+                let default = self.config.lock().unwrap().default_strategy.clone();
+                self.use_strategy(&default)
+            }
+            Method::Pause => {
+                {
+                    let mut actuator = self.actuator.lock().unwrap();
+                    if let Err(e) = actuator.restore_auto() {
+                        error!("{} failed to restore auto control: {}", actuator.name(), e);
+                    }
+                }
+                *self.paused.lock().unwrap() = true;
+                Ok(Reply::Done)
+            }
+            Method::Resume => {
+                *self.paused.lock().unwrap() = false;
+                self.fan_thread.unpark();
+                Ok(Reply::Done)
+            }
+            Method::Reload => {
+                reload_config(
+                    &self.config_path,
+                    &self.config,
+                    &self.temp_source,
+                    &self.actuator,
+                )
+                .map_err(|e| {
+                    RpcError::new(
+                        ErrorCode::ReloadFailed,
+                        format!("Failed to reload config: {}", e),
+                    )
+                })?;
+                Ok(Reply::Done)
+            }
+            Method::Tool { args } => {
+                let output = Command::new("framework_tool")
+                    .args(args)
+                    .output()
+                    .map_err(|e| {
+                        RpcError::new(
+                            ErrorCode::ToolFailed,
+                            format!("framework_tool failed: {}", e),
+                        )
+                    })?;
+                Ok(Reply::Tool(ToolOutput {
+                    status: output.status.code(),
+                    stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+                    stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+                }))
+            }
+        }
+    }
+
+    /// Serves one client. A connection whose first byte is `{` speaks the
+    /// line-delimited JSON protocol for as long as it stays open; anything
+    /// else is a single plain text command, answered in plain text.
+    pub fn serve(&self, stream: UnixStream) -> std::io::Result<()> {
+        stream.set_read_timeout(Some(CLIENT_IDLE_TIMEOUT))?;
+        stream.set_write_timeout(Some(CLIENT_IDLE_TIMEOUT))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = stream;
+
+        let first = reader.fill_buf()?;
+        if first.is_empty() {
+            return Ok(());
+        }
+        if first.iter().find(|b| !b.is_ascii_whitespace()) != Some(&b'{') {
+            let text = String::from_utf8_lossy(first).to_string();
+            let reply = match TextCommand::parse(&text) {
+                Some(command) => {
+                    info!("received: {}", text.trim());
+                    command.render(&self.handle(&command.method))
+                }
+                None => "unknown or unfinished argument".to_string(),
+            };
+            return writer.write_all(reply.as_bytes());
+        }
+
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if (&mut reader).take(MAX_REQUEST_LEN).read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+            if !line.ends_with('\n') && line.len() as u64 >= MAX_REQUEST_LEN {
+                let error = RpcError::new(ErrorCode::ParseError, "Request too large");
+                return write_response(&mut writer, None, Err(error));
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let (id, result) = match parse_request(&line) {
+                Ok(request) => (request.id, self.handle(&request.method)),
+                Err((id, e)) => (id, Err(e)),
+            };
+            let refused = matches!(&result, Err(e) if e.code == ErrorCode::UnsupportedVersion);
+            write_response(&mut writer, id, result)?;
+            if refused {
+                return Ok(());
+            }
+        }
+    }
+}
+
+fn write_response(
+    writer: &mut UnixStream,
+    id: Option<u64>,
+    result: Result<Reply, RpcError>,
+) -> std::io::Result<()> {
+    let outcome = match result {
+        Ok(value) => Outcome::Result(value),
+        Err(e) => Outcome::Error(e),
+    };
+    let mut msg = serde_json::to_vec(&Response { id, outcome })?;
+    msg.push(b'\n');
+    writer.write_all(&msg)
+}
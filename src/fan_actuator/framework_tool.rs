@@ -0,0 +1,56 @@
+use std::process::Command;
+
+use log::debug;
+
+use super::FanActuator;
+use crate::temp_source::framework_tool::parse_temp;
+
+/// Drives the EC through `framework_tool --fansetduty` / `--fansetrpm` /
+/// `--autofanctrl`.
+pub struct FrameworkToolActuator;
+
+fn run(args: &[&str]) -> std::io::Result<()> {
+    let output = Command::new("framework_tool").args(args).output()?;
+    let stderr = std::str::from_utf8(&output.stderr).unwrap_or("<invalid utf8>");
+    debug!("stderr: {}", stderr);
+    if !output.status.success() {
+        return Err(std::io::Error::other(format!(
+            "framework_tool {} failed: {}",
+            args.join(" "),
+            stderr.trim()
+        )));
+    }
+    Ok(())
+}
+
+/// `framework_tool <flag> [fan] <value>`, omitting the fan targets all fans.
+fn run_for_fan(flag: &str, fan: Option<u32>, value: u32) -> std::io::Result<()> {
+    let value = value.to_string();
+    match fan {
+        Some(fan) => run(&[flag, &fan.to_string(), &value]),
+        None => run(&[flag, &value]),
+    }
+}
+
+impl FanActuator for FrameworkToolActuator {
+    fn name(&self) -> &'static str {
+        "framework_tool"
+    }
+
+    fn set_duty(&mut self, fan: Option<u32>, percent: u8) -> std::io::Result<()> {
+        run_for_fan("--fansetduty", fan, percent as u32)
+    }
+
+    fn set_rpm(&mut self, fan: Option<u32>, rpm: u32) -> std::io::Result<()> {
+        run_for_fan("--fansetrpm", fan, rpm)
+    }
+
+    fn restore_auto(&mut self) -> std::io::Result<()> {
+        run(&["--autofanctrl"])
+    }
+
+    fn read_rpm(&mut self) -> std::io::Result<Vec<u32>> {
+        let output = Command::new("framework_tool").arg("--thermal").output()?;
+        Ok(parse_temp(&String::from_utf8_lossy(&output.stdout)).fan_speeds)
+    }
+}
@@ -0,0 +1,63 @@
+use super::FanActuator;
+
+/// In-memory fans that report an rpm proportional to their duty.
+pub struct MockActuator {
+    max_rpm: u32,
+    duties: Vec<u8>,
+    auto: bool,
+}
+
+impl MockActuator {
+    pub fn new(max_rpm: u32, fans: u32) -> Self {
+        Self {
+            max_rpm,
+            duties: vec![0; fans.max(1) as usize],
+            auto: true,
+        }
+    }
+}
+
+impl FanActuator for MockActuator {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn set_duty(&mut self, fan: Option<u32>, percent: u8) -> std::io::Result<()> {
+        let percent = percent.min(100);
+        match fan {
+            Some(fan) => {
+                let duty = self.duties.get_mut(fan as usize).ok_or_else(|| {
+                    std::io::Error::new(std::io::ErrorKind::NotFound, format!("no fan {}", fan))
+                })?;
+                *duty = percent;
+            }
+            None => self.duties.fill(percent),
+        }
+        self.auto = false;
+        Ok(())
+    }
+
+    /// Recorded as the duty that reaches `rpm`.
+    fn set_rpm(&mut self, fan: Option<u32>, rpm: u32) -> std::io::Result<()> {
+        let percent = (rpm.min(self.max_rpm) * 100)
+            .checked_div(self.max_rpm)
+            .unwrap_or(0);
+        self.set_duty(fan, percent as u8)
+    }
+
+    fn restore_auto(&mut self) -> std::io::Result<()> {
+        self.auto = true;
+        Ok(())
+    }
+
+    fn read_rpm(&mut self) -> std::io::Result<Vec<u32>> {
+        if self.auto {
+            return Ok(vec![0; self.duties.len()]);
+        }
+        Ok(self
+            .duties
+            .iter()
+            .map(|duty| self.max_rpm * *duty as u32 / 100)
+            .collect())
+    }
+}
@@ -0,0 +1,36 @@
+use crate::fan_config::FanActuatorConfig;
+
+pub mod framework_tool;
+pub mod mock;
+pub mod sysfs;
+
+pub trait FanActuator: Send {
+    fn name(&self) -> &'static str;
+    /// Takes manual control and sets the duty in percent (0-100) of `fan`,
+    /// or of every fan when `fan` is `None`.
+    fn set_duty(&mut self, fan: Option<u32>, percent: u8) -> std::io::Result<()>;
+    /// Takes manual control and sets the target speed of `fan`, or of every
+    /// fan when `fan` is `None`. Fails where the hardware has no rpm target.
+    fn set_rpm(&mut self, fan: Option<u32>, rpm: u32) -> std::io::Result<()>;
+    /// Hands fan control back to the firmware / driver.
+    fn restore_auto(&mut self) -> std::io::Result<()>;
+    /// Measured rpm of every fan, indexed by fan.
+    fn read_rpm(&mut self) -> std::io::Result<Vec<u32>>;
+    /// Whether `set_duty(None, ..)` reaches every fan, so fans without a
+    /// duty of their own can be driven through it.
+    fn untargeted_covers_all(&self) -> bool {
+        true
+    }
+}
+
+pub fn from_config(config: &FanActuatorConfig) -> Box<dyn FanActuator> {
+    match config {
+        FanActuatorConfig::FrameworkTool => Box::new(framework_tool::FrameworkToolActuator),
+        FanActuatorConfig::SysfsPwm { root, chip, index } => {
+            Box::new(sysfs::SysfsPwmActuator::new(root, chip, *index))
+        }
+        FanActuatorConfig::Mock { max_rpm, fans } => {
+            Box::new(mock::MockActuator::new(*max_rpm, *fans))
+        }
+    }
+}
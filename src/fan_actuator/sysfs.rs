@@ -0,0 +1,292 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::{info, warn};
+
+use super::FanActuator;
+
+const PWM_ENABLE_MANUAL: &str = "1";
+const PWM_ENABLE_AUTO: &str = "2";
+
+/// Drives `pwmN` of an hwmon chip found under `root`.
+///
+/// `chip` is matched against each `hwmon*/name` (or the directory name itself),
+/// since the `hwmonN` numbering is not stable across boots. Fan `i` maps to
+/// channel `index + i`; untargeted writes go to channel `index`.
+pub struct SysfsPwmActuator {
+    root: PathBuf,
+    chip: String,
+    index: u32,
+    hwmon: Option<PathBuf>,
+    /// `pwmN_enable` of each channel as found before we took manual control.
+    saved_enable: BTreeMap<u32, String>,
+}
+
+impl SysfsPwmActuator {
+    pub fn new(root: &str, chip: &str, index: u32) -> Self {
+        Self {
+            root: PathBuf::from(root),
+            chip: chip.to_string(),
+            index,
+            hwmon: None,
+            saved_enable: BTreeMap::new(),
+        }
+    }
+
+    fn hwmon(&mut self) -> std::io::Result<&Path> {
+        if self.hwmon.is_none() {
+            let found = find_chip(&self.root, &self.chip)?;
+            info!("Using {} for pwm{}", found.display(), self.index);
+            self.hwmon = Some(found);
+        }
+        Ok(self.hwmon.as_deref().unwrap())
+    }
+
+    fn attr(&mut self, name: &str) -> std::io::Result<PathBuf> {
+        Ok(self.hwmon()?.join(name))
+    }
+
+    fn channel(&self, fan: Option<u32>) -> u32 {
+        self.index + fan.unwrap_or(0)
+    }
+
+    fn take_manual(&mut self, channel: u32) -> std::io::Result<()> {
+        if self.saved_enable.contains_key(&channel) {
+            return Ok(());
+        }
+        let enable = self.attr(&format!("pwm{}_enable", channel))?;
+        let current = fs::read_to_string(&enable)?.trim().to_string();
+        fs::write(&enable, PWM_ENABLE_MANUAL)?;
+        self.saved_enable.insert(channel, current);
+        Ok(())
+    }
+}
+
+/// The saved `pwmN_enable` if it is one of the driver's automatic modes
+/// (2 and up), else plain automatic. A channel that was already manual or
+/// at full speed, e.g. after a crash, must not be left that way.
+fn auto_mode(saved: &str) -> &str {
+    match saved.parse::<u32>() {
+        Ok(mode) if mode >= 2 => saved,
+        _ => PWM_ENABLE_AUTO,
+    }
+}
+
+fn find_chip(root: &Path, chip: &str) -> std::io::Result<PathBuf> {
+    for entry in fs::read_dir(root)? {
+        let path = entry?.path();
+        let dir_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+        let name = fs::read_to_string(path.join("name")).unwrap_or_default();
+        if dir_name == chip || name.trim() == chip {
+            return Ok(path);
+        }
+    }
+
+    Err(std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        format!("no hwmon chip named {} under {}", chip, root.display()),
+    ))
+}
+
+impl FanActuator for SysfsPwmActuator {
+    fn name(&self) -> &'static str {
+        "sysfs_pwm"
+    }
+
+    fn set_duty(&mut self, fan: Option<u32>, percent: u8) -> std::io::Result<()> {
+        let channel = self.channel(fan);
+        self.take_manual(channel)?;
+        let raw = (percent.min(100) as u32 * 255 + 50) / 100;
+        fs::write(self.attr(&format!("pwm{}", channel))?, raw.to_string())
+    }
+
+    /// Only chips with a `fanN_target` attribute take an rpm.
+    fn set_rpm(&mut self, fan: Option<u32>, rpm: u32) -> std::io::Result<()> {
+        let channel = self.channel(fan);
+        let target = self.attr(&format!("fan{}_target", channel))?;
+        if !target.exists() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                format!("{} does not exist", target.display()),
+            ));
+        }
+        self.take_manual(channel)?;
+        fs::write(target, rpm.to_string())
+    }
+
+    /// Tries every channel; the ones that fail stay saved so a later call,
+    /// or the drop, retries them. Returns the first error.
+    fn restore_auto(&mut self) -> std::io::Result<()> {
+        let mut saved = std::mem::take(&mut self.saved_enable);
+        let taken = !saved.is_empty();
+        if !taken {
+            saved.insert(self.index, PWM_ENABLE_AUTO.to_string());
+        }
+        let mut first_error = None;
+        for (channel, mode) in saved {
+            let restored = self
+                .attr(&format!("pwm{}_enable", channel))
+                .and_then(|enable| fs::write(enable, auto_mode(&mode)));
+            if let Err(e) = restored {
+                if taken {
+                    self.saved_enable.insert(channel, mode);
+                }
+                first_error.get_or_insert(e);
+            }
+        }
+        first_error.map_or(Ok(()), Err)
+    }
+
+    fn read_rpm(&mut self) -> std::io::Result<Vec<u32>> {
+        let mut rpm = Vec::new();
+        for channel in self.index.. {
+            let input = self.attr(&format!("fan{}_input", channel))?;
+            let Ok(raw) = fs::read_to_string(input) else {
+                break;
+            };
+            rpm.push(raw.trim().parse().unwrap_or(0));
+        }
+        Ok(rpm)
+    }
+
+    /// Untargeted writes only reach channel `index`.
+    fn untargeted_covers_all(&self) -> bool {
+        false
+    }
+}
+
+impl Drop for SysfsPwmActuator {
+    fn drop(&mut self) {
+        if !self.saved_enable.is_empty() {
+            if let Err(e) = self.restore_auto() {
+                warn!("Failed to restore pwm_enable: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fake_chip(enable: &str) -> tempfile::TempDir {
+        let root = tempfile::tempdir().unwrap();
+        let chip = root.path().join("hwmon3");
+        fs::create_dir_all(&chip).unwrap();
+        fs::write(chip.join("name"), "nct6775\n").unwrap();
+        for channel in 1..=2 {
+            fs::write(chip.join(format!("pwm{}", channel)), "0\n").unwrap();
+            fs::write(chip.join(format!("pwm{}_enable", channel)), enable).unwrap();
+            fs::write(
+                chip.join(format!("fan{}_input", channel)),
+                format!("{}\n", channel * 1000),
+            )
+            .unwrap();
+        }
+        root
+    }
+
+    fn read(root: &Path, attr: &str) -> String {
+        fs::read_to_string(root.join("hwmon3").join(attr)).unwrap()
+    }
+
+    #[test]
+    fn scales_duty_to_pwm() {
+        let dir = fake_chip("2\n");
+        let root = dir.path();
+        let mut actuator = SysfsPwmActuator::new(root.to_str().unwrap(), "nct6775", 1);
+
+        actuator.set_duty(None, 50).unwrap();
+        assert_eq!(read(root, "pwm1"), "128");
+        actuator.set_duty(Some(1), 100).unwrap();
+        assert_eq!(read(root, "pwm2"), "255");
+        actuator.set_duty(None, 0).unwrap();
+        assert_eq!(read(root, "pwm1"), "0");
+    }
+
+    #[test]
+    fn sets_rpm_only_with_a_target() {
+        let dir = fake_chip("2\n");
+        let root = dir.path();
+        let mut actuator = SysfsPwmActuator::new(root.to_str().unwrap(), "nct6775", 1);
+
+        let err = actuator.set_rpm(None, 1500).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::Unsupported);
+        assert_eq!(read(root, "pwm1_enable"), "2\n");
+
+        fs::write(root.join("hwmon3/fan2_target"), "0\n").unwrap();
+        actuator.set_rpm(Some(1), 1500).unwrap();
+        assert_eq!(read(root, "fan2_target"), "1500");
+        assert_eq!(read(root, "pwm2_enable"), "1");
+    }
+
+    #[test]
+    fn switches_enable_and_restores_it() {
+        let dir = fake_chip("5\n");
+        let root = dir.path();
+        let mut actuator = SysfsPwmActuator::new(root.to_str().unwrap(), "hwmon3", 1);
+
+        actuator.set_duty(None, 40).unwrap();
+        assert_eq!(read(root, "pwm1_enable"), "1");
+        assert_eq!(read(root, "pwm2_enable"), "5\n");
+
+        actuator.restore_auto().unwrap();
+        assert_eq!(read(root, "pwm1_enable"), "5");
+    }
+
+    #[test]
+    fn failed_channels_are_retried() {
+        let dir = fake_chip("2\n");
+        let root = dir.path();
+        let mut actuator = SysfsPwmActuator::new(root.to_str().unwrap(), "nct6775", 1);
+        actuator.set_duty(Some(0), 40).unwrap();
+        actuator.set_duty(Some(1), 40).unwrap();
+
+        // a directory can't be written, not even by root
+        let enable = root.join("hwmon3/pwm1_enable");
+        fs::remove_file(&enable).unwrap();
+        fs::create_dir(&enable).unwrap();
+        assert!(actuator.restore_auto().is_err());
+        assert_eq!(read(root, "pwm2_enable"), "2");
+
+        fs::remove_dir(&enable).unwrap();
+        drop(actuator);
+        assert_eq!(read(root, "pwm1_enable"), "2");
+    }
+
+    #[test]
+    fn manual_enable_is_restored_as_auto() {
+        let dir = fake_chip("1\n");
+        let root = dir.path();
+        let mut actuator = SysfsPwmActuator::new(root.to_str().unwrap(), "nct6775", 1);
+
+        actuator.set_duty(Some(0), 40).unwrap();
+        actuator.set_duty(Some(1), 40).unwrap();
+        drop(actuator);
+
+        assert_eq!(read(root, "pwm1_enable"), "2");
+        assert_eq!(read(root, "pwm2_enable"), "2");
+    }
+
+    #[test]
+    fn reads_rpm_from_index() {
+        let dir = fake_chip("2\n");
+        let root = dir.path();
+        let path = root.to_str().unwrap();
+
+        assert_eq!(
+            SysfsPwmActuator::new(path, "nct6775", 1)
+                .read_rpm()
+                .unwrap(),
+            vec![1000, 2000]
+        );
+        assert_eq!(
+            SysfsPwmActuator::new(path, "nct6775", 2)
+                .read_rpm()
+                .unwrap(),
+            vec![2000]
+        );
+        assert!(SysfsPwmActuator::new(path, "it87", 1).read_rpm().is_err());
+    }
+}
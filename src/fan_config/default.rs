@@ -18,6 +18,7 @@ pub fn default_fan_config() -> FanConfig {
                 SpeedPoint { temp: 75.0, speed: 50.0 },
                 SpeedPoint { temp: 85.0, speed: 100.0 },
             ],
+            ..Default::default()
         },
     );
 
@@ -34,6 +35,7 @@ pub fn default_fan_config() -> FanConfig {
                 SpeedPoint { temp: 75.0, speed: 50.0 },
                 SpeedPoint { temp: 85.0, speed: 100.0 },
             ],
+            ..Default::default()
         },
     );
 
@@ -50,6 +52,7 @@ pub fn default_fan_config() -> FanConfig {
                 SpeedPoint { temp: 75.0, speed: 80.0 },
                 SpeedPoint { temp: 85.0, speed: 100.0 },
             ],
+            ..Default::default()
         },
     );
 
@@ -66,6 +69,7 @@ pub fn default_fan_config() -> FanConfig {
                 SpeedPoint { temp: 75.0, speed: 80.0 },
                 SpeedPoint { temp: 85.0, speed: 100.0 },
             ],
+            ..Default::default()
         },
     );
 
@@ -82,6 +86,7 @@ pub fn default_fan_config() -> FanConfig {
                 SpeedPoint { temp: 75.0, speed: 80.0 },
                 SpeedPoint { temp: 85.0, speed: 100.0 },
             ],
+            ..Default::default()
         },
     );
 
@@ -96,6 +101,7 @@ pub fn default_fan_config() -> FanConfig {
                 SpeedPoint { temp: 50.0, speed: 50.0 },
                 SpeedPoint { temp: 60.0, speed: 100.0 },
             ],
+            ..Default::default()
         },
     );
 
@@ -109,6 +115,7 @@ pub fn default_fan_config() -> FanConfig {
                 SpeedPoint { temp: 40.0, speed: 50.0 },
                 SpeedPoint { temp: 65.0, speed: 100.0 },
             ],
+            ..Default::default()
         },
     );
 
@@ -116,5 +123,12 @@ pub fn default_fan_config() -> FanConfig {
         default_strategy: "lazy".to_string(),
         strategy_on_discharging: "".to_string(),
         strategies,
+        temperature_source: TemperatureSourceConfig::default(),
+        fan_actuator: FanActuatorConfig::default(),
+        power_supply_root: default_power_supply_root(),
+        critical_temp: None,
+        failure_policy: FailurePolicy::default(),
+        heartbeat_secs: None,
+        subscriber_queue: SubscriberQueue::default(),
     }
 }
\ No newline at end of file
@@ -2,7 +2,7 @@ use ron::ser::{to_string_pretty};
 use config::{Config as ConfigLoader, File};
 use serde::{Deserialize, Serialize};
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::path::Path;
 
 pub mod default;
 
@@ -12,11 +12,496 @@ pub struct SpeedPoint {
     pub speed: f32,
 }
 
+/// A temperature fed into a strategy. `offset` is added to the raw reading,
+/// `weight` only matters for `Aggregation::WeightedAverage`. A missing
+/// `required` sensor counts as a failed update instead of being skipped.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SensorInput {
+    pub name: String,
+    #[serde(default)]
+    pub offset: f32,
+    #[serde(default = "default_weight")]
+    pub weight: f32,
+    #[serde(default)]
+    pub required: bool,
+}
+
+impl SensorInput {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            offset: 0.0,
+            weight: default_weight(),
+            required: false,
+        }
+    }
+}
+
+fn default_weight() -> f32 {
+    1.0
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum Aggregation {
+    #[default]
+    Max,
+    WeightedAverage,
+}
+
+/// A curve driven by a single sensor, see `Strategy::curves`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SensorCurve {
+    pub sensor: String,
+    #[serde(default)]
+    pub offset: f32,
+    /// Fan index this curve drives; `None` drives every fan. Fans no
+    /// untargeted curve reaches and that have none of their own follow the
+    /// highest speed of all curves, on actuators that can set every fan at
+    /// once (see `Strategy::fan_targets`).
+    #[serde(default)]
+    pub fan: Option<u32>,
+    pub speed_curve: Vec<SpeedPoint>,
+}
+
+/// How a strategy turns temperatures into a fan speed.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum ControlMode {
+    /// Interpolate the speed curves and smooth the result.
+    #[default]
+    Curve,
+    /// Drive the aggregated temperature towards `target_temp`.
+    Pid {
+        target_temp: f32,
+        kp: f32,
+        #[serde(default)]
+        ki: f32,
+        #[serde(default)]
+        kd: f32,
+        #[serde(default)]
+        min_duty: f32,
+        #[serde(default = "default_max_duty")]
+        max_duty: f32,
+    },
+}
+
+fn default_max_duty() -> f32 {
+    100.0
+}
+
+impl ControlMode {
+    fn validate(&self) -> Result<(), String> {
+        let ControlMode::Pid {
+            target_temp,
+            kp,
+            ki,
+            kd,
+            min_duty,
+            max_duty,
+        } = *self
+        else {
+            return Ok(());
+        };
+        if ![target_temp, kp, ki, kd].iter().all(|v| v.is_finite()) {
+            return Err("pid target_temp and gains must be finite".to_string());
+        }
+        check_duty("pid min_duty", min_duty)?;
+        check_duty("pid max_duty", max_duty)?;
+        if min_duty > max_duty {
+            return Err(format!(
+                "pid min_duty {} is above max_duty {}",
+                min_duty, max_duty
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// How speeds between two `SpeedPoint`s are computed.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum Interpolation {
+    #[default]
+    Linear,
+    /// Keep the speed of a point until the next point is reached.
+    Step,
+    /// Fritsch–Carlson monotone cubic spline.
+    MonotoneCubic,
+    Smoothstep,
+}
+
+/// How the buffered curve speeds are averaged.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum Smoothing {
+    /// Mean after dropping `trim_ratio` of the samples at each end.
+    TrimmedMean {
+        #[serde(default = "default_trim_ratio")]
+        trim_ratio: f32,
+    },
+    Mean,
+    Median,
+    /// Exponential moving average whose time constant is the window.
+    Ema,
+}
+
+impl Default for Smoothing {
+    fn default() -> Self {
+        Smoothing::TrimmedMean {
+            trim_ratio: default_trim_ratio(),
+        }
+    }
+}
+
+fn default_trim_ratio() -> f32 {
+    0.2
+}
+
+/// Above `temp` every fan is forced to 100 % until the temperature falls
+/// below `temp - hysteresis`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CriticalTemp {
+    pub temp: f32,
+    #[serde(default = "default_critical_hysteresis")]
+    pub hysteresis: f32,
+}
+
+fn default_critical_hysteresis() -> f32 {
+    5.0
+}
+
+/// A short burst of duty to get a stopped fan turning.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SpinUpKick {
+    pub duty: f32,
+    pub duration_secs: f32,
+}
+
+/// Longest duration accepted anywhere in the config.
+const MAX_SECS: f32 = 86_400.0;
+
+/// Durations end up in a `Duration`, which can't be negative, NaN or huge.
+fn check_secs(name: &str, secs: f32) -> Result<(), String> {
+    if !(0.0..=MAX_SECS).contains(&secs) {
+        return Err(format!("{} must be within 0-{} seconds", name, MAX_SECS));
+    }
+    Ok(())
+}
+
+fn check_duty(name: &str, duty: f32) -> Result<(), String> {
+    if !(0.0..=100.0).contains(&duty) {
+        return Err(format!("{} must be within 0-100", name));
+    }
+    Ok(())
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Strategy {
     pub fan_speed_update_frequency: f32,
     pub moving_average_interval: u32,
+    /// Smoothing window in seconds; replaces the `moving_average_interval`
+    /// sample count so the window no longer depends on the update frequency.
+    #[serde(default)]
+    pub smoothing_window_secs: Option<f32>,
+    #[serde(default)]
+    pub smoothing: Smoothing,
+    #[serde(default)]
     pub speed_curve: Vec<SpeedPoint>,
+    #[serde(default)]
+    pub interpolation: Interpolation,
+    #[serde(default = "default_sensors")]
+    pub sensors: Vec<SensorInput>,
+    #[serde(default)]
+    pub aggregation: Aggregation,
+    /// When set, each curve is evaluated against its own sensor and the
+    /// highest speed wins; `speed_curve`, `sensors` and `aggregation` are ignored.
+    #[serde(default)]
+    pub curves: Vec<SensorCurve>,
+    #[serde(default)]
+    pub control: ControlMode,
+    /// Degrees the temperature has to fall below the point where a speed was
+    /// reached before the curve is allowed to lower it again.
+    #[serde(default)]
+    pub hysteresis: f32,
+    /// Fastest the fan speed may rise / fall, in percent per second.
+    #[serde(default)]
+    pub max_increase_per_sec: Option<f32>,
+    #[serde(default)]
+    pub max_decrease_per_sec: Option<f32>,
+    /// Lowest duty a spinning fan is given; requests below it but above 0
+    /// are raised to it so the fan does not stall.
+    #[serde(default)]
+    pub min_running_duty: Option<f32>,
+    #[serde(default)]
+    pub spin_up_kick: Option<SpinUpKick>,
+    /// When set, the fan stops below this temperature and only starts again
+    /// above `start_above_temp` (defaults to `stop_below_temp`).
+    #[serde(default)]
+    pub stop_below_temp: Option<f32>,
+    #[serde(default)]
+    pub start_above_temp: Option<f32>,
+    /// Overrides `FanConfig::critical_temp` for this strategy.
+    #[serde(default)]
+    pub critical_temp: Option<CriticalTemp>,
+}
+
+impl Strategy {
+    /// Fans that need their own controller, in the order they must be set.
+    /// `[None]` means one duty for all fans, which is what strategies without
+    /// fan-specific curves get. Otherwise every fan with a curve of its own
+    /// is driven on its own. When some of the actuator's `fan_count` fans
+    /// have none and `untargeted_covers_all`, an untargeted duty comes first
+    /// for them, then the targeted ones override it; other actuators leave
+    /// those fans alone.
+    pub fn fan_targets(&self, fan_count: usize, untargeted_covers_all: bool) -> Vec<Option<u32>> {
+        let own: std::collections::BTreeSet<u32> =
+            self.curves.iter().filter_map(|c| c.fan).collect();
+        if own.is_empty() {
+            return vec![None];
+        }
+        let uncovered = (0..fan_count as u32).any(|fan| !own.contains(&fan));
+        (uncovered && untargeted_covers_all)
+            .then_some(None)
+            .into_iter()
+            .chain(own.into_iter().map(Some))
+            .collect()
+    }
+
+    /// Rejects values the controller can't act on.
+    pub fn validate(&self) -> Result<(), String> {
+        check_secs(
+            "fan_speed_update_frequency",
+            self.fan_speed_update_frequency,
+        )?;
+        if let Some(window) = self.smoothing_window_secs {
+            check_secs("smoothing_window_secs", window)?;
+        }
+        self.control.validate()?;
+        let rates = [
+            ("max_increase_per_sec", self.max_increase_per_sec),
+            ("max_decrease_per_sec", self.max_decrease_per_sec),
+        ];
+        for (name, rate) in rates {
+            if rate.is_some_and(|rate| !(rate.is_finite() && rate >= 0.0)) {
+                return Err(format!("{} must be finite and not negative", name));
+            }
+        }
+        if let Some(kick) = &self.spin_up_kick {
+            check_duty("spin_up_kick duty", kick.duty)?;
+            check_secs("spin_up_kick duration_secs", kick.duration_secs)?;
+        }
+        Ok(())
+    }
+
+    /// Every sensor this strategy reads, through `sensors` or `curves`.
+    pub fn sensor_names(&self) -> impl Iterator<Item = &str> {
+        self.sensors
+            .iter()
+            .map(|s| s.name.as_str())
+            .chain(self.curves.iter().map(|c| c.sensor.as_str()))
+    }
+}
+
+/// The `framework_tool` sensor names. None of them is required, so other
+/// backends without an `APU` alias still work through the strategy's
+/// remaining sensors or `curves`. An unpowered dGPU reports no reading, so
+/// without `APU` the update fails rather than running on 0 °C.
+fn default_sensors() -> Vec<SensorInput> {
+    vec![SensorInput::new("APU"), SensorInput::new("dGPU_temp")]
+}
+
+impl Default for Strategy {
+    fn default() -> Self {
+        Self {
+            fan_speed_update_frequency: 2.0,
+            moving_average_interval: 30,
+            smoothing_window_secs: None,
+            smoothing: Smoothing::default(),
+            speed_curve: vec![],
+            interpolation: Interpolation::default(),
+            sensors: default_sensors(),
+            aggregation: Aggregation::default(),
+            curves: vec![],
+            control: ControlMode::default(),
+            hysteresis: 0.0,
+            max_increase_per_sec: None,
+            max_decrease_per_sec: None,
+            min_running_duty: None,
+            spin_up_kick: None,
+            stop_below_temp: None,
+            start_above_temp: None,
+            critical_temp: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(tag = "backend", rename_all = "snake_case")]
+pub enum TemperatureSourceConfig {
+    #[default]
+    FrameworkTool,
+    /// `sensors` maps a name used by strategies (e.g. `APU`) to a
+    /// `"<chip> <label>"` key such as `k10temp Tctl`.
+    Hwmon {
+        #[serde(default = "default_hwmon_root")]
+        root: String,
+        #[serde(default)]
+        sensors: std::collections::HashMap<String, String>,
+    },
+    ThermalZone {
+        #[serde(default = "default_thermal_root")]
+        root: String,
+    },
+    Mock {
+        sensors: std::collections::HashMap<String, f32>,
+        #[serde(default)]
+        fan_speeds: Vec<u32>,
+    },
+}
+
+fn default_hwmon_root() -> String {
+    "/sys/class/hwmon".to_string()
+}
+
+fn default_thermal_root() -> String {
+    "/sys/class/thermal".to_string()
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(tag = "backend", rename_all = "snake_case")]
+pub enum FanActuatorConfig {
+    #[default]
+    FrameworkTool,
+    /// `chip` is an hwmon `name` (e.g. `nct6775`) or directory (e.g. `hwmon3`).
+    SysfsPwm {
+        #[serde(default = "default_hwmon_root")]
+        root: String,
+        chip: String,
+        #[serde(default = "default_pwm_index")]
+        index: u32,
+    },
+    Mock {
+        #[serde(default = "default_mock_max_rpm")]
+        max_rpm: u32,
+        #[serde(default = "default_mock_fans")]
+        fans: u32,
+    },
+}
+
+fn default_pwm_index() -> u32 {
+    1
+}
+
+fn default_mock_max_rpm() -> u32 {
+    5000
+}
+
+fn default_mock_fans() -> u32 {
+    1
+}
+
+/// What to do with the fans once updates keep failing.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Failsafe {
+    /// Hand control back to the EC / driver.
+    #[default]
+    AutoControl,
+    FixedDuty {
+        duty: u8,
+    },
+    /// Hold every fan at `rpm`, on actuators that take an rpm target.
+    FixedRpm {
+        rpm: u32,
+    },
+}
+
+/// Failed updates are retried with exponential backoff; after
+/// `max_failures` in a row the failsafe is applied until an update succeeds.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FailurePolicy {
+    #[serde(default = "default_max_failures")]
+    pub max_failures: u32,
+    #[serde(default = "default_retry_initial_secs")]
+    pub retry_initial_secs: f32,
+    #[serde(default = "default_retry_max_secs")]
+    pub retry_max_secs: f32,
+    #[serde(default)]
+    pub failsafe: Failsafe,
+}
+
+impl FailurePolicy {
+    fn validate(&self) -> Result<(), String> {
+        check_secs("retry_initial_secs", self.retry_initial_secs)?;
+        check_secs("retry_max_secs", self.retry_max_secs)?;
+        if let Failsafe::FixedDuty { duty } = self.failsafe {
+            check_duty("failsafe duty", duty as f32)?;
+        }
+        Ok(())
+    }
+
+    pub fn retry_delay(&self, failures: u32) -> f32 {
+        let exp = failures.saturating_sub(1).min(16) as i32;
+        (self.retry_initial_secs * 2f32.powi(exp)).min(self.retry_max_secs)
+    }
+}
+
+impl Default for FailurePolicy {
+    fn default() -> Self {
+        Self {
+            max_failures: default_max_failures(),
+            retry_initial_secs: default_retry_initial_secs(),
+            retry_max_secs: default_retry_max_secs(),
+            failsafe: Failsafe::default(),
+        }
+    }
+}
+
+fn default_max_failures() -> u32 {
+    3
+}
+
+fn default_retry_initial_secs() -> f32 {
+    0.5
+}
+
+fn default_retry_max_secs() -> f32 {
+    10.0
+}
+
+/// What to do when an info socket subscriber's queue is full.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(tag = "policy", rename_all = "snake_case")]
+pub enum Overflow {
+    /// Drop the oldest queued event, so a slow reader skips ahead.
+    #[default]
+    DropOldest,
+    /// Hang up on the reader.
+    Disconnect,
+}
+
+/// Events waiting to be written to each info socket subscriber. Read at
+/// startup.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SubscriberQueue {
+    #[serde(default = "default_queue_capacity")]
+    pub capacity: usize,
+    #[serde(default)]
+    pub overflow: Overflow,
+}
+
+impl Default for SubscriberQueue {
+    fn default() -> Self {
+        Self {
+            capacity: default_queue_capacity(),
+            overflow: Overflow::default(),
+        }
+    }
+}
+
+fn default_queue_capacity() -> usize {
+    64
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -24,26 +509,58 @@ pub struct FanConfig {
     pub default_strategy: String,
     pub strategy_on_discharging: String,
     pub strategies: std::collections::HashMap<String, Strategy>,
+    #[serde(default)]
+    pub temperature_source: TemperatureSourceConfig,
+    #[serde(default)]
+    pub fan_actuator: FanActuatorConfig,
+    #[serde(default = "default_power_supply_root")]
+    pub power_supply_root: String,
+    #[serde(default)]
+    pub critical_temp: Option<CriticalTemp>,
+    #[serde(default)]
+    pub failure_policy: FailurePolicy,
+    /// Resend the status to info socket subscribers this often, even when
+    /// nothing changed.
+    #[serde(default)]
+    pub heartbeat_secs: Option<f32>,
+    #[serde(default)]
+    pub subscriber_queue: SubscriberQueue,
 }
 
-fn get_config_file() -> PathBuf {
-    let mut path = PathBuf::from("/etc/fw-fanctrl-rs");
-    fs::create_dir_all(&path).unwrap();
-    path.push("config.ron");
-    path
+fn default_power_supply_root() -> String {
+    "/sys/class/power_supply".to_string()
+}
+
+impl FanConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, strategy) in &self.strategies {
+            strategy
+                .validate()
+                .map_err(|e| format!("strategy {}: {}", name, e))?;
+        }
+        self.failure_policy
+            .validate()
+            .map_err(|e| format!("failure_policy: {}", e))?;
+        if let Some(secs) = self.heartbeat_secs {
+            check_secs("heartbeat_secs", secs)?;
+        }
+        Ok(())
+    }
 }
 
 fn write_config<P: AsRef<Path>>(path: P, config: &FanConfig) -> std::io::Result<()> {
     let ron_string = to_string_pretty(config, ron::ser::PrettyConfig::default())
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        .map_err(std::io::Error::other)?;
     fs::write(path, ron_string)
 }
 
-pub fn load_or_create_config() -> Result<FanConfig, Box<dyn std::error::Error>> {
-    let path = get_config_file();
+pub fn load_or_create_config(path: &Path) -> Result<FanConfig, Box<dyn std::error::Error>> {
     if !path.exists() {
         let default = default::default_fan_config();
-        write_config(&path, &default)?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        write_config(path, &default)?;
         return Ok(default);
     }
 
@@ -51,6 +568,132 @@ pub fn load_or_create_config() -> Result<FanConfig, Box<dyn std::error::Error>>
         .add_source(File::with_name(path.to_str().unwrap()))
         .build()?
         .try_deserialize::<FanConfig>()?;
+    loaded.validate()?;
 
     Ok(loaded)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pid(min_duty: f32, max_duty: f32, kp: f32) -> Strategy {
+        Strategy {
+            control: ControlMode::Pid {
+                target_temp: 70.0,
+                kp,
+                ki: 0.0,
+                kd: 0.0,
+                min_duty,
+                max_duty,
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn default_config_is_valid() {
+        default::default_fan_config().validate().unwrap();
+    }
+
+    #[test]
+    fn fans_without_a_curve_need_an_untargeted_write() {
+        let strategy = |fans: &[Option<u32>]| Strategy {
+            curves: fans
+                .iter()
+                .map(|&fan| SensorCurve {
+                    sensor: "APU".to_string(),
+                    offset: 0.0,
+                    fan,
+                    speed_curve: vec![],
+                })
+                .collect(),
+            ..Default::default()
+        };
+        assert_eq!(strategy(&[None]).fan_targets(3, false), vec![None]);
+        assert_eq!(
+            strategy(&[Some(1), None]).fan_targets(3, true),
+            vec![None, Some(1)]
+        );
+        // a hwmon source may see fans the actuator doesn't drive
+        assert_eq!(strategy(&[Some(1)]).fan_targets(3, false), vec![Some(1)]);
+        assert_eq!(
+            strategy(&[Some(0), Some(1)]).fan_targets(2, true),
+            vec![Some(0), Some(1)]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_pid() {
+        assert!(pid(20.0, 80.0, 2.0).validate().is_ok());
+        assert!(pid(80.0, 20.0, 2.0).validate().is_err());
+        assert!(pid(0.0, 120.0, 2.0).validate().is_err());
+        assert!(pid(f32::NAN, 80.0, 2.0).validate().is_err());
+        assert!(pid(0.0, 100.0, f32::INFINITY).validate().is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_smoothing_window() {
+        let window = |secs| Strategy {
+            smoothing_window_secs: Some(secs),
+            ..Default::default()
+        };
+        assert!(window(30.0).validate().is_ok());
+        assert!(window(-1.0).validate().is_err());
+        assert!(window(f32::NAN).validate().is_err());
+        assert!(window(f32::INFINITY).validate().is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_slew_rates() {
+        let rates = |increase, decrease| Strategy {
+            max_increase_per_sec: increase,
+            max_decrease_per_sec: decrease,
+            ..Default::default()
+        };
+        assert!(rates(Some(20.0), Some(0.0)).validate().is_ok());
+        assert!(rates(Some(-5.0), None).validate().is_err());
+        assert!(rates(None, Some(f32::NAN)).validate().is_err());
+        assert!(rates(Some(f32::INFINITY), None).validate().is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_spin_up_kick() {
+        let kick = |duty, duration_secs| Strategy {
+            spin_up_kick: Some(SpinUpKick {
+                duty,
+                duration_secs,
+            }),
+            ..Default::default()
+        };
+        assert!(kick(60.0, 2.0).validate().is_ok());
+        assert!(kick(60.0, -1.0).validate().is_err());
+        assert!(kick(60.0, f32::NAN).validate().is_err());
+        assert!(kick(60.0, 1e30).validate().is_err());
+        assert!(kick(150.0, 2.0).validate().is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_failure_policy() {
+        let policy = |retry_initial_secs, duty| FailurePolicy {
+            retry_initial_secs,
+            failsafe: Failsafe::FixedDuty { duty },
+            ..Default::default()
+        };
+        assert!(policy(0.5, 100).validate().is_ok());
+        assert!(policy(-0.5, 100).validate().is_err());
+        assert!(policy(f32::NAN, 100).validate().is_err());
+        assert!(policy(0.5, 101).validate().is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_heartbeat() {
+        let mut config = default::default_fan_config();
+        for secs in [f32::INFINITY, f32::NAN, -1.0, 1e30] {
+            config.heartbeat_secs = Some(secs);
+            assert!(config.validate().is_err(), "{}", secs);
+        }
+        config.heartbeat_secs = Some(0.0);
+        assert!(config.validate().is_ok());
+    }
+}
@@ -1,50 +1,381 @@
 use crate::fan_config::*;
+use crate::temp_source::TempReadings;
+use log::debug;
 use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+#[derive(Default)]
+struct PidState {
+    integral: f32,
+    last_temp: Option<f32>,
+    last_update: Option<Instant>,
+}
+
+/// Tracks whether the critical temperature override is engaged.
+#[derive(Default)]
+pub struct CriticalOverride {
+    active: bool,
+}
+
+impl CriticalOverride {
+    /// Returns the new state when the override engages or releases.
+    pub fn update(&mut self, temperature: f32, critical: Option<&CriticalTemp>) -> Option<bool> {
+        let active = match critical {
+            Some(critical) if self.active => temperature >= critical.temp - critical.hysteresis,
+            Some(critical) => temperature >= critical.temp,
+            None => false,
+        };
+        if active == self.active {
+            return None;
+        }
+        self.active = active;
+        Some(active)
+    }
+
+    pub fn active(&self) -> bool {
+        self.active
+    }
+}
 
 pub struct FanController {
-    buffer: VecDeque<f32>,
+    buffer: VecDeque<(Instant, f32)>,
+    ema: Option<(f32, Instant)>,
+    pid: PidState,
+    /// Curve speed held by hysteresis.
+    held_speed: Option<f32>,
+    /// Last returned speed, for the slew-rate limits.
+    last_output: Option<(f32, Instant)>,
+    /// Whether the fan was last left spinning, for zero-rpm mode.
+    running: bool,
+    kick_until: Option<Instant>,
+    /// Temperature that decided the last update: the aggregate of
+    /// `sensors`, or in `curves` mode the reading of the winning curve.
+    temperature: f32,
+    /// Speed asked for by the curve or PID loop in the last update, before
+    /// smoothing and limits.
+    raw_speed: f32,
 }
 
 impl FanController {
     pub fn new(strategy: &Strategy) -> Self {
         Self {
             buffer: VecDeque::with_capacity(strategy.moving_average_interval as usize),
+            ema: None,
+            pid: PidState::default(),
+            held_speed: None,
+            last_output: None,
+            running: false,
+            kick_until: None,
+            temperature: 0.0,
+            raw_speed: 0.0,
         }
     }
 
-    pub fn update(&mut self, temperature: f32, strategy: &Strategy) -> f32 {
-        let fan_speed: f32 = self.interpolate(temperature, strategy);
+    pub fn temperature(&self) -> f32 {
+        self.temperature
+    }
+
+    pub fn raw_speed(&self) -> f32 {
+        self.raw_speed
+    }
+
+    pub fn update(
+        &mut self,
+        readings: &TempReadings,
+        strategy: &Strategy,
+        fan: Option<u32>,
+    ) -> f32 {
+        self.update_at(readings, strategy, fan, Instant::now())
+    }
+
+    /// Records a duty sent in place of the last update's, e.g. by the
+    /// critical override, so the slew limits ramp down from it afterwards.
+    pub fn force(&mut self, duty: f32) {
+        self.force_at(duty, Instant::now())
+    }
+
+    fn force_at(&mut self, duty: f32, now: Instant) {
+        self.running = duty > 0.0;
+        self.kick_until = None;
+        self.last_output = Some((duty, now));
+    }
+
+    fn update_at(
+        &mut self,
+        readings: &TempReadings,
+        strategy: &Strategy,
+        fan: Option<u32>,
+        now: Instant,
+    ) -> f32 {
+        let aggregate = readings
+            .aggregate(&strategy.sensors, &strategy.aggregation)
+            .unwrap_or(0.0);
+        let (raw_speed, fan_speed, temperature) = match strategy.control {
+            ControlMode::Pid { .. } => {
+                let fan_speed = self.pid_update(aggregate, strategy, now);
+                (fan_speed, fan_speed, aggregate)
+            }
+            ControlMode::Curve => {
+                let (raw_speed, temperature) = self.curve_update(readings, strategy, fan);
+                let fan_speed = self.smooth(raw_speed, strategy, now);
+                (raw_speed, fan_speed, temperature.unwrap_or(aggregate))
+            }
+        };
+        self.temperature = temperature;
+        self.raw_speed = raw_speed;
 
-        // add to buffer
-        self.buffer.push_back(fan_speed);
-        if self.buffer.len() > strategy.moving_average_interval as usize {
-            self.buffer.pop_front();
+        let fan_speed = self.zero_rpm(fan_speed, temperature, strategy, now);
+        self.limit_slew(fan_speed, strategy, now)
+    }
+
+    /// Decides whether the fan spins at all, keeps a spinning fan above its
+    /// stall duty and kicks it when starting from standstill.
+    fn zero_rpm(
+        &mut self,
+        requested: f32,
+        temperature: f32,
+        strategy: &Strategy,
+        now: Instant,
+    ) -> f32 {
+        let running = match strategy.stop_below_temp {
+            Some(stop) if self.running => temperature >= stop,
+            Some(stop) => temperature > strategy.start_above_temp.unwrap_or(stop),
+            None => requested > 0.0,
+        };
+
+        if !running {
+            if self.running {
+                debug!("zero rpm: stopping fan at {}", temperature);
+            }
+            self.running = false;
+            self.kick_until = None;
+            return 0.0;
         }
 
-        // trimmed moving average like Smooth rule
-        let mut values: Vec<f32> = self.buffer.iter().copied().collect();
-        values.sort_by(|a, b| a.partial_cmp(b).unwrap());
-        let len = values.len();
+        if !self.running {
+            if let Some(kick) = &strategy.spin_up_kick {
+                debug!(
+                    "zero rpm: kicking fan at {} for {}s",
+                    kick.duty, kick.duration_secs
+                );
+                self.kick_until = Some(now + Duration::from_secs_f32(kick.duration_secs));
+            }
+        }
+        self.running = true;
+
+        let mut fan_speed = requested.max(strategy.min_running_duty.unwrap_or(0.0));
+        if let (Some(until), Some(kick)) = (self.kick_until, &strategy.spin_up_kick) {
+            if now < until {
+                fan_speed = fan_speed.max(kick.duty);
+            } else {
+                self.kick_until = None;
+            }
+        }
+        fan_speed
+    }
+
+    fn limit_slew(&mut self, target: f32, strategy: &Strategy, now: Instant) -> f32 {
+        let mut fan_speed = target;
+        if let Some((last, at)) = self.last_output {
+            let dt = now.duration_since(at).as_secs_f32();
+            // a spin-up kick jumps straight to its duty
+            if let (Some(max_increase), None) = (strategy.max_increase_per_sec, self.kick_until) {
+                fan_speed = fan_speed.min(last + max_increase * dt);
+            }
+            if let Some(max_decrease) = strategy.max_decrease_per_sec {
+                fan_speed = fan_speed.max(last - max_decrease * dt);
+            }
+        }
+        if self.running {
+            fan_speed = fan_speed.max(strategy.min_running_duty.unwrap_or(0.0));
+        }
+        self.last_output = Some((fan_speed, now));
+        fan_speed
+    }
+
+    /// The curve speed after hysteresis, and the temperature that picked it.
+    fn curve_update(
+        &mut self,
+        readings: &TempReadings,
+        strategy: &Strategy,
+        fan: Option<u32>,
+    ) -> (f32, Option<f32>) {
+        // the curve speed now, and the one it would be `hysteresis` degrees hotter
+        let (rising, temperature) = Self::curve_speed(readings, strategy, fan, 0.0);
+        let (falling, _) = Self::curve_speed(readings, strategy, fan, strategy.hysteresis);
+        let fan_speed = match self.held_speed {
+            // still within `hysteresis` degrees of where the held speed was reached
+            Some(held) if falling >= held => rising.max(held),
+            _ => rising,
+        };
+        self.held_speed = Some(fan_speed);
+        (fan_speed, temperature)
+    }
+
+    fn smooth(&mut self, fan_speed: f32, strategy: &Strategy, now: Instant) -> f32 {
+        // add to buffer, dropping samples outside the window
+        self.buffer.push_back((now, fan_speed));
+        match strategy.smoothing_window_secs {
+            Some(window) => {
+                while self
+                    .buffer
+                    .front()
+                    .is_some_and(|(at, _)| now.duration_since(*at).as_secs_f32() > window)
+                {
+                    self.buffer.pop_front();
+                }
+            }
+            None => {
+                while self.buffer.len() > strategy.moving_average_interval as usize {
+                    self.buffer.pop_front();
+                }
+            }
+        }
+
+        let len = self.buffer.len();
         if len == 0 {
             return 0.0;
         }
 
-        let trimmed: &[f32] = if len > 2 {
-            let cut = std::cmp::max(1, len / 5);
-            &values[cut..len - cut]
-        } else {
-            &values[..]
+        match strategy.smoothing {
+            Smoothing::TrimmedMean { trim_ratio } => {
+                let mut values: Vec<f32> = self.buffer.iter().map(|(_, v)| *v).collect();
+                values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+                let trimmed: &[f32] = if len > 2 && trim_ratio > 0.0 {
+                    let cut = ((len as f32 * trim_ratio) as usize).clamp(1, (len - 1) / 2);
+                    &values[cut..len - cut]
+                } else {
+                    &values[..]
+                };
+                trimmed.iter().sum::<f32>() / trimmed.len() as f32
+            }
+            Smoothing::Mean => self.buffer.iter().map(|(_, v)| v).sum::<f32>() / len as f32,
+            Smoothing::Median => {
+                let mut values: Vec<f32> = self.buffer.iter().map(|(_, v)| *v).collect();
+                values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                if len.is_multiple_of(2) {
+                    (values[len / 2 - 1] + values[len / 2]) / 2.0
+                } else {
+                    values[len / 2]
+                }
+            }
+            Smoothing::Ema => {
+                // time constant is the window, so irregular ticks weigh correctly
+                let tau = strategy.smoothing_window_secs.unwrap_or(
+                    strategy.moving_average_interval as f32 * strategy.fan_speed_update_frequency,
+                );
+                let ema = match self.ema {
+                    Some((last, at)) if tau > 0.0 => {
+                        let dt = now.duration_since(at).as_secs_f32();
+                        let alpha = 1.0 - (-dt / tau).exp();
+                        last + alpha * (fan_speed - last)
+                    }
+                    _ => fan_speed,
+                };
+                self.ema = Some((ema, now));
+                ema
+            }
+        }
+    }
+
+    fn pid_update(&mut self, temperature: f32, strategy: &Strategy, now: Instant) -> f32 {
+        let ControlMode::Pid {
+            target_temp,
+            kp,
+            ki,
+            kd,
+            min_duty,
+            max_duty,
+        } = strategy.control
+        else {
+            return 0.0;
         };
-        let avg: f32 = trimmed.iter().sum::<f32>() / trimmed.len() as f32;
-        avg
+
+        let dt = self
+            .pid
+            .last_update
+            .map(|last| now.duration_since(last).as_secs_f32())
+            .unwrap_or(strategy.fan_speed_update_frequency)
+            .max(f32::EPSILON);
+
+        let error = temperature - target_temp;
+        // derivative on measurement, so changing the target does not kick the fan
+        let derivative = self
+            .pid
+            .last_temp
+            .map(|last| (temperature - last) / dt)
+            .unwrap_or(0.0);
+
+        let integral = self.pid.integral + error * dt;
+        let output = kp * error + ki * integral + kd * derivative;
+
+        // anti-windup: stop integrating while saturated in the direction of the error
+        let saturated = (output > max_duty && error > 0.0) || (output < min_duty && error < 0.0);
+        if !saturated {
+            self.pid.integral = integral;
+        }
+        self.pid.last_temp = Some(temperature);
+        self.pid.last_update = Some(now);
+
+        let duty = output.clamp(min_duty, max_duty);
+        debug!(
+            "pid: temp {} error {} integral {} -> {}",
+            temperature, error, self.pid.integral, duty
+        );
+        duty
+    }
+
+    /// The speed of the curves `offset` degrees hotter, along with the
+    /// temperature of the curve that asked for it. `None` when no sensor of
+    /// the `curves` was read.
+    fn curve_speed(
+        readings: &TempReadings,
+        strategy: &Strategy,
+        fan: Option<u32>,
+        offset: f32,
+    ) -> (f32, Option<f32>) {
+        if strategy.curves.is_empty() {
+            let temperature = readings
+                .aggregate(&strategy.sensors, &strategy.aggregation)
+                .unwrap_or(0.0);
+            debug!("temp: {:?}", temperature);
+            let speed = Self::interpolate(
+                temperature + offset,
+                &strategy.speed_curve,
+                &strategy.interpolation,
+            );
+            return (speed, Some(temperature));
+        }
+
+        let applies = |curve: &SensorCurve| curve.fan.is_none() || curve.fan == fan;
+        // a fan no curve reaches falls back to all of them
+        let fallback = !strategy.curves.iter().any(applies);
+        strategy
+            .curves
+            .iter()
+            .filter(|curve| fallback || applies(curve))
+            .filter_map(|curve| {
+                let temperature = readings.get(&curve.sensor)? + curve.offset;
+                let speed = Self::interpolate(
+                    temperature + offset,
+                    &curve.speed_curve,
+                    &strategy.interpolation,
+                );
+                debug!("{}: {} -> {}", curve.sensor, temperature, speed);
+                Some((speed, temperature))
+            })
+            .reduce(|best, next| if next.0 > best.0 { next } else { best })
+            .map_or((0.0, None), |(speed, temperature)| {
+                (speed, Some(temperature))
+            })
     }
 
-    fn interpolate(&self, temperature: f32, strategy: &Strategy) -> f32 {
-        if strategy.speed_curve.is_empty() {
+    fn interpolate(temperature: f32, points: &[SpeedPoint], mode: &Interpolation) -> f32 {
+        if points.is_empty() {
             return 0.0;
         }
 
-        let points = &strategy.speed_curve;
         if temperature <= points[0].temp {
             return points[0].speed;
         }
@@ -57,10 +388,445 @@ impl FanController {
             let b = &points[i + 1];
             if temperature >= a.temp && temperature <= b.temp {
                 let t = (temperature - a.temp) / (b.temp - a.temp);
-                return a.speed + t * (b.speed - a.speed);
+                return match mode {
+                    Interpolation::Linear => a.speed + t * (b.speed - a.speed),
+                    Interpolation::Step => {
+                        if t >= 1.0 {
+                            b.speed
+                        } else {
+                            a.speed
+                        }
+                    }
+                    Interpolation::Smoothstep => {
+                        let t = t * t * (3.0 - 2.0 * t);
+                        a.speed + t * (b.speed - a.speed)
+                    }
+                    Interpolation::MonotoneCubic => {
+                        let tangents = monotone_tangents(points);
+                        let h = b.temp - a.temp;
+                        let (t2, t3) = (t * t, t * t * t);
+                        (2.0 * t3 - 3.0 * t2 + 1.0) * a.speed
+                            + (t3 - 2.0 * t2 + t) * h * tangents[i]
+                            + (-2.0 * t3 + 3.0 * t2) * b.speed
+                            + (t3 - t2) * h * tangents[i + 1]
+                    }
+                };
             }
         }
 
         points.last().unwrap().speed
     }
 }
+
+/// Fritsch–Carlson tangents, which keep the cubic Hermite spline monotone
+/// between points so the curve never overshoots them.
+fn monotone_tangents(points: &[SpeedPoint]) -> Vec<f32> {
+    let n = points.len();
+    let secants: Vec<f32> = points
+        .windows(2)
+        .map(|w| {
+            let h = w[1].temp - w[0].temp;
+            if h > 0.0 {
+                (w[1].speed - w[0].speed) / h
+            } else {
+                0.0
+            }
+        })
+        .collect();
+
+    let mut tangents = vec![0.0; n];
+    if n < 2 {
+        return tangents;
+    }
+    tangents[0] = secants[0];
+    tangents[n - 1] = secants[n - 2];
+    for k in 1..n - 1 {
+        if secants[k - 1] * secants[k] > 0.0 {
+            tangents[k] = (secants[k - 1] + secants[k]) / 2.0;
+        }
+    }
+
+    for k in 0..n - 1 {
+        if secants[k] == 0.0 {
+            tangents[k] = 0.0;
+            tangents[k + 1] = 0.0;
+            continue;
+        }
+        let alpha = tangents[k] / secants[k];
+        let beta = tangents[k + 1] / secants[k];
+        let norm = alpha * alpha + beta * beta;
+        if norm > 9.0 {
+            let tau = 3.0 / norm.sqrt();
+            tangents[k] = tau * alpha * secants[k];
+            tangents[k + 1] = tau * beta * secants[k];
+        }
+    }
+
+    tangents
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strategy(hysteresis: f32) -> Strategy {
+        Strategy {
+            moving_average_interval: 1,
+            speed_curve: vec![
+                SpeedPoint {
+                    temp: 40.0,
+                    speed: 0.0,
+                },
+                SpeedPoint {
+                    temp: 60.0,
+                    speed: 100.0,
+                },
+            ],
+            sensors: vec![SensorInput::new("APU")],
+            hysteresis,
+            ..Default::default()
+        }
+    }
+
+    fn readings(apu: f32) -> TempReadings {
+        let mut readings = TempReadings::default();
+        readings.sensors.insert("APU".to_string(), apu);
+        readings
+    }
+
+    /// Feeds `(seconds since start, APU temperature)` steps to a fresh
+    /// controller, returning it and the speed it gave at every step.
+    fn run_at(strategy: &Strategy, steps: &[(u64, f32)]) -> (FanController, Vec<f32>) {
+        let mut ctrl = FanController::new(strategy);
+        let start = Instant::now();
+        let speeds = steps
+            .iter()
+            .map(|(secs, temp)| {
+                let now = start + Duration::from_secs(*secs);
+                ctrl.update_at(&readings(*temp), strategy, None, now)
+            })
+            .collect();
+        (ctrl, speeds)
+    }
+
+    /// One step per second.
+    fn run(strategy: &Strategy, temps: &[f32]) -> Vec<f32> {
+        let steps: Vec<_> = temps
+            .iter()
+            .enumerate()
+            .map(|(i, t)| (i as u64, *t))
+            .collect();
+        run_at(strategy, &steps).1
+    }
+
+    #[test]
+    fn reports_raw_speed_before_smoothing() {
+        let strategy = Strategy {
+            moving_average_interval: 100,
+            smoothing_window_secs: Some(2.0),
+            smoothing: Smoothing::Mean,
+            ..strategy(0.0)
+        };
+        let (ctrl, speeds) = run_at(&strategy, &[(0, 60.0), (1, 40.0)]);
+
+        assert_eq!(speeds, vec![100.0, 50.0]);
+        assert_eq!(ctrl.raw_speed(), 0.0);
+        assert_eq!(ctrl.temperature(), 40.0);
+    }
+
+    #[test]
+    fn reports_temperature_of_winning_curve() {
+        let curve = |sensor: &str, offset| SensorCurve {
+            sensor: sensor.to_string(),
+            offset,
+            fan: None,
+            speed_curve: strategy(0.0).speed_curve,
+        };
+        let strategy = Strategy {
+            curves: vec![curve("APU", 0.0), curve("dGPU", 5.0)],
+            ..strategy(0.0)
+        };
+        let mut readings = readings(52.0);
+        readings.sensors.insert("dGPU".to_string(), 50.0);
+
+        let mut ctrl = FanController::new(&strategy);
+        assert_eq!(ctrl.update(&readings, &strategy, None), 75.0);
+        assert_eq!(ctrl.temperature(), 55.0);
+    }
+
+    #[test]
+    fn critical_override_releases_below_hysteresis() {
+        let critical = CriticalTemp {
+            temp: 90.0,
+            hysteresis: 5.0,
+        };
+        let mut critical_override = CriticalOverride::default();
+        let changes: Vec<_> = [89.0, 90.0, 88.0, 85.0, 84.9, 89.0]
+            .iter()
+            .map(|temp| critical_override.update(*temp, Some(&critical)))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![None, Some(true), None, None, Some(false), None]
+        );
+
+        critical_override.update(95.0, Some(&critical));
+        assert!(critical_override.active());
+        assert_eq!(critical_override.update(95.0, None), Some(false));
+    }
+
+    #[test]
+    fn forced_duty_ramps_down_through_slew() {
+        let strategy = Strategy {
+            max_decrease_per_sec: Some(10.0),
+            ..strategy(0.0)
+        };
+        let mut ctrl = FanController::new(&strategy);
+        let start = Instant::now();
+        assert_eq!(
+            ctrl.update_at(&readings(50.0), &strategy, None, start),
+            50.0
+        );
+        ctrl.force_at(100.0, start);
+
+        let after = |secs| start + Duration::from_secs(secs);
+        assert_eq!(
+            ctrl.update_at(&readings(50.0), &strategy, None, after(1)),
+            90.0
+        );
+        assert_eq!(
+            ctrl.update_at(&readings(50.0), &strategy, None, after(2)),
+            80.0
+        );
+    }
+
+    fn pid(kp: f32, ki: f32, min_duty: f32, max_duty: f32) -> Strategy {
+        Strategy {
+            fan_speed_update_frequency: 1.0,
+            control: ControlMode::Pid {
+                target_temp: 70.0,
+                kp,
+                ki,
+                kd: 0.0,
+                min_duty,
+                max_duty,
+            },
+            ..strategy(0.0)
+        }
+    }
+
+    #[test]
+    fn pid_settles_on_target() {
+        // heats towards 90 °C, every percent of duty takes 0.05 °C a second off
+        let strategy = pid(2.0, 0.5, 0.0, 100.0);
+        let mut ctrl = FanController::new(&strategy);
+        let start = Instant::now();
+        let mut temp = 85.0;
+        let mut duty = 0.0;
+        for secs in 0..90 {
+            let now = start + Duration::from_secs(secs);
+            duty = ctrl.update_at(&readings(temp), &strategy, None, now);
+            temp += 0.1 * (90.0 - temp) - 0.05 * duty;
+        }
+        assert!((temp - 70.0).abs() < 0.1, "settled at {}", temp);
+        assert!((duty - 40.0).abs() < 0.5, "settled at {}%", duty);
+    }
+
+    #[test]
+    fn pid_does_not_wind_up_while_saturated() {
+        let strategy = pid(5.0, 1.0, 0.0, 100.0);
+        let mut steps: Vec<_> = (0..20).map(|secs| (secs, 90.0)).collect();
+        steps.push((20, 69.0));
+        let (ctrl, speeds) = run_at(&strategy, &steps);
+
+        assert!(speeds[..20].iter().all(|speed| *speed == 100.0));
+        // an integral wound up over 20 s at +20 °C would still hold 100 %
+        assert_eq!(ctrl.pid.integral, 0.0);
+        assert_eq!(speeds[20], 0.0);
+    }
+
+    #[test]
+    fn pid_clamps_to_duty_range() {
+        let strategy = pid(5.0, 0.0, 20.0, 80.0);
+        let speeds = run(&strategy, &[95.0, 70.0, 72.0, 40.0]);
+        assert_eq!(speeds, vec![80.0, 20.0, 20.0, 20.0]);
+    }
+
+    #[test]
+    fn slew_limits_are_asymmetric() {
+        let strategy = Strategy {
+            max_increase_per_sec: Some(20.0),
+            max_decrease_per_sec: Some(5.0),
+            ..strategy(0.0)
+        };
+        let (_, speeds) = run_at(
+            &strategy,
+            &[(0, 40.0), (1, 60.0), (3, 60.0), (4, 40.0), (6, 40.0)],
+        );
+        assert_eq!(speeds, vec![0.0, 20.0, 60.0, 55.0, 45.0]);
+    }
+
+    #[test]
+    fn smoothing_window_is_in_seconds() {
+        let strategy = Strategy {
+            moving_average_interval: 100,
+            smoothing_window_secs: Some(2.0),
+            smoothing: Smoothing::Mean,
+            ..strategy(0.0)
+        };
+        let speeds = run(&strategy, &[60.0, 40.0, 40.0, 40.0]);
+        assert_eq!(speeds, vec![100.0, 50.0, 100.0 / 3.0, 0.0]);
+    }
+
+    /// Curve speeds 0, 100, 100, 50, 50 averaged over the last five.
+    fn smoothed(smoothing: Smoothing) -> Vec<f32> {
+        let strategy = Strategy {
+            moving_average_interval: 5,
+            smoothing,
+            ..strategy(0.0)
+        };
+        run(&strategy, &[40.0, 60.0, 60.0, 50.0, 50.0])
+    }
+
+    #[test]
+    fn trimmed_mean_drops_both_ends() {
+        let speeds = smoothed(Smoothing::TrimmedMean { trim_ratio: 0.2 });
+        assert_eq!(speeds, vec![0.0, 50.0, 100.0, 75.0, 200.0 / 3.0]);
+
+        // never trims everything away
+        let speeds = smoothed(Smoothing::TrimmedMean { trim_ratio: 0.5 });
+        assert_eq!(speeds[4], 50.0);
+
+        let speeds = smoothed(Smoothing::TrimmedMean { trim_ratio: 0.0 });
+        assert_eq!(speeds[4], 60.0);
+    }
+
+    #[test]
+    fn median_of_even_count_is_midpoint() {
+        let speeds = smoothed(Smoothing::Median);
+        assert_eq!(speeds, vec![0.0, 50.0, 100.0, 75.0, 50.0]);
+    }
+
+    #[test]
+    fn ema_weighs_by_elapsed_time() {
+        let strategy = Strategy {
+            smoothing_window_secs: Some(2.0),
+            smoothing: Smoothing::Ema,
+            ..strategy(0.0)
+        };
+        let (_, speeds) = run_at(&strategy, &[(0, 60.0), (1, 40.0), (3, 40.0)]);
+        let decayed = [100.0, 100.0 * (-0.5f32).exp(), 100.0 * (-1.5f32).exp()];
+        for (speed, expected) in speeds.iter().zip(decayed) {
+            assert!((speed - expected).abs() < 1e-3, "{} != {}", speed, expected);
+        }
+    }
+
+    fn knee() -> Vec<SpeedPoint> {
+        [(40.0, 0.0), (50.0, 0.0), (60.0, 50.0), (70.0, 100.0)]
+            .iter()
+            .map(|(temp, speed)| SpeedPoint {
+                temp: *temp,
+                speed: *speed,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn step_holds_until_next_point() {
+        let points = knee();
+        let speed = |t| FanController::interpolate(t, &points, &Interpolation::Step);
+        assert_eq!(speed(59.9), 0.0);
+        assert_eq!(speed(60.0), 50.0);
+        assert_eq!(speed(65.0), 50.0);
+        assert_eq!(speed(80.0), 100.0);
+    }
+
+    #[test]
+    fn monotone_cubic_does_not_overshoot() {
+        let points = knee();
+        let mut last = 0.0;
+        for i in 0..=400 {
+            let t = 40.0 + i as f32 * 0.075;
+            let speed = FanController::interpolate(t, &points, &Interpolation::MonotoneCubic);
+            assert!((0.0..=100.0).contains(&speed), "{} at {}", speed, t);
+            assert!(speed >= last - 1e-4, "{} < {} at {}", speed, last, t);
+            if t <= 50.0 {
+                assert!(speed.abs() < 1e-4, "{} at {}", speed, t);
+            }
+            last = speed;
+        }
+    }
+
+    #[test]
+    fn zero_rpm_kicks_then_holds_min_duty() {
+        let strategy = Strategy {
+            min_running_duty: Some(20.0),
+            spin_up_kick: Some(SpinUpKick {
+                duty: 60.0,
+                duration_secs: 2.0,
+            }),
+            ..strategy(0.0)
+        };
+        let speeds = run(&strategy, &[40.0, 42.0, 42.0, 42.0, 50.0, 40.0]);
+        assert_eq!(speeds, vec![0.0, 60.0, 60.0, 20.0, 50.0, 0.0]);
+    }
+
+    #[test]
+    fn zero_rpm_stop_and_start_thresholds() {
+        let strategy = Strategy {
+            min_running_duty: Some(20.0),
+            stop_below_temp: Some(42.0),
+            start_above_temp: Some(48.0),
+            ..strategy(0.0)
+        };
+        let speeds = run(&strategy, &[45.0, 49.0, 45.0, 42.0, 41.0, 45.0]);
+        assert_eq!(speeds, vec![0.0, 45.0, 25.0, 20.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn zero_rpm_stop_and_kick_go_through_slew() {
+        let strategy = Strategy {
+            max_increase_per_sec: Some(10.0),
+            max_decrease_per_sec: Some(10.0),
+            min_running_duty: Some(20.0),
+            spin_up_kick: Some(SpinUpKick {
+                duty: 60.0,
+                duration_secs: 2.0,
+            }),
+            stop_below_temp: Some(45.0),
+            ..strategy(0.0)
+        };
+        let speeds = run(&strategy, &[40.0, 50.0, 50.0, 46.0, 44.0, 44.0]);
+        assert_eq!(speeds, vec![0.0, 60.0, 60.0, 50.0, 40.0, 30.0]);
+    }
+
+    #[test]
+    fn without_hysteresis_follows_curve() {
+        let speeds = run(&strategy(0.0), &[50.0, 55.0, 50.0, 45.0]);
+        assert_eq!(speeds, vec![50.0, 75.0, 50.0, 25.0]);
+    }
+
+    #[test]
+    fn hysteresis_increases_immediately() {
+        let speeds = run(&strategy(5.0), &[45.0, 50.0, 55.0]);
+        assert_eq!(speeds, vec![25.0, 50.0, 75.0]);
+    }
+
+    #[test]
+    fn hysteresis_holds_decrease_inside_band() {
+        let speeds = run(&strategy(5.0), &[55.0, 54.0, 52.0, 50.0]);
+        assert_eq!(speeds, vec![75.0, 75.0, 75.0, 75.0]);
+    }
+
+    #[test]
+    fn hysteresis_decreases_below_band() {
+        let speeds = run(&strategy(5.0), &[55.0, 48.0, 42.0]);
+        assert_eq!(speeds, vec![75.0, 40.0, 10.0]);
+    }
+
+    #[test]
+    fn hysteresis_band_follows_latest_increase() {
+        let speeds = run(&strategy(5.0), &[50.0, 55.0, 58.0, 54.0, 48.0]);
+        assert_eq!(speeds, vec![50.0, 75.0, 90.0, 90.0, 40.0]);
+    }
+}
@@ -0,0 +1,143 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, Write};
+use std::os::unix::fs::{FileTypeExt, OpenOptionsExt};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+
+use log::warn;
+
+/// Held for the daemon's lifetime; the flock is released when the file closes.
+pub struct InstanceLock {
+    _file: File,
+}
+
+fn read_pid(file: &mut File) -> Option<u32> {
+    let mut contents = String::new();
+    file.rewind().ok()?;
+    file.read_to_string(&mut contents).ok()?;
+    contents.trim().parse().ok()
+}
+
+/// Takes an exclusive flock on `path` and writes our pid into it. Fails with
+/// the running daemon's pid when another instance holds the lock. A symlink
+/// at `path` is refused, so nobody can point the root daemon at another file.
+pub fn acquire(path: &Path) -> std::io::Result<InstanceLock> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .mode(0o644)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(path)?;
+
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+        let err = std::io::Error::last_os_error();
+        if err.kind() != std::io::ErrorKind::WouldBlock {
+            return Err(err);
+        }
+        let pid = read_pid(&mut file).map_or("unknown".to_string(), |p| p.to_string());
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("daemon already running (pid {})", pid),
+        ));
+    }
+
+    file.set_len(0)?;
+    file.rewind()?;
+    writeln!(file, "{}", std::process::id())?;
+    Ok(InstanceLock { _file: file })
+}
+
+/// Removes `path` if it is a socket nobody listens on anymore, e.g. left
+/// behind by a daemon that was killed. Anything else at `path`, including a
+/// symlink, is refused: the path may come from `--socket` and a typo must
+/// not delete some other file as root.
+pub fn remove_stale_socket(path: &Path) -> std::io::Result<()> {
+    let file_type = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata.file_type(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    if !file_type.is_socket() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("{} exists and is not a socket", path.display()),
+        ));
+    }
+
+    match UnixStream::connect(path) {
+        Ok(_) => Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("daemon already listening on {}", path.display()),
+        )),
+        Err(_) => {
+            warn!("Removing stale socket {}", path.display());
+            fs::remove_file(path)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixListener;
+
+    #[test]
+    fn second_instance_reports_pid() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("fw-fanctrl.pid");
+        let lock = acquire(&path).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            format!("{}\n", std::process::id())
+        );
+
+        let err = acquire(&path).err().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+        assert!(err
+            .to_string()
+            .contains(&format!("pid {}", std::process::id())));
+
+        drop(lock);
+        acquire(&path).unwrap();
+    }
+
+    #[test]
+    fn symlinked_lock_is_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("target");
+        let path = dir.path().join("fw-fanctrl.pid");
+        fs::write(&target, "keep").unwrap();
+        std::os::unix::fs::symlink(&target, &path).unwrap();
+
+        assert!(acquire(&path).is_err());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "keep");
+    }
+
+    #[test]
+    fn only_stale_sockets_are_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("fw-fanctrl.sock");
+        remove_stale_socket(&path).unwrap();
+
+        let listener = UnixListener::bind(&path).unwrap();
+        let err = remove_stale_socket(&path).err().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+
+        drop(listener);
+        remove_stale_socket(&path).unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn other_files_are_never_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("fw-fanctrl.sock");
+        fs::write(&path, "keep").unwrap();
+        let err = remove_stale_socket(&path).err().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "keep");
+    }
+}
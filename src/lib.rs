@@ -0,0 +1,55 @@
+//! Client side of fw-fanctrl-rs: the socket protocol and the types the
+//! daemon reports, for tools that talk to a running daemon.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+pub mod client;
+pub mod paths;
+pub mod protocol;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FanStatus {
+    pub index: u32,
+    /// Duty sent to the fan after smoothing, limits and overrides.
+    pub target: u8,
+    pub rpm: Option<u32>,
+    /// Temperature that decided this fan's speed: the strategy's aggregated
+    /// sensors, or in `curves` mode the offset reading of the winning curve.
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    /// Speed the curve or PID loop asked for, before smoothing and limits.
+    #[serde(default)]
+    pub raw_speed: Option<f32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Status {
+    pub strategy: String,
+    pub speed: u8,
+    pub paused: bool,
+    pub fans: Vec<FanStatus>,
+    pub degraded: bool,
+    /// Hottest effective temperature across the fans.
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub sensors: BTreeMap<String, f32>,
+    /// Unix time in milliseconds of the last fan update, 0 before the first.
+    #[serde(default)]
+    pub timestamp: u64,
+    #[serde(default)]
+    pub temperature_source: String,
+    #[serde(default)]
+    pub fan_actuator: String,
+}
+
+/// Messages pushed to info socket subscribers.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    Status(Status),
+    PowerSource { on_battery: bool, strategy: String },
+    CriticalTemp { active: bool, temperature: f32 },
+}
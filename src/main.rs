@@ -1,247 +1,357 @@
-use std::env;
+use std::collections::BTreeMap;
 use std::fs;
-use std::io::BufRead;
-use std::io::BufReader;
-use std::io::{Read, Write};
-use std::os::unix::fs::PermissionsExt;
-use std::os::unix::net::{UnixListener, UnixStream};
-use std::path::Path;
-use std::process::exit;
-use std::process::Command;
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::os::unix::net::UnixListener;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::mpsc;
 use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Duration;
-
-use crate::fan_config::Strategy;
-use crate::fan_control::FanController;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::broadcast::Broadcaster;
+use crate::control::Control;
+use crate::fan_actuator::FanActuator;
+use crate::fan_config::{Failsafe, FanConfig, Strategy};
+use crate::fan_control::{CriticalOverride, FanController};
+use crate::power_supply::PowerSwitch;
+use crate::shutdown::Shutdown;
+use crate::temp_source::{TempReadings, TemperatureSource};
+use fw_fanctrl::client::{self, Client};
+use fw_fanctrl::paths::Paths;
+use fw_fanctrl::protocol::TextCommand;
+use fw_fanctrl::{Event, FanStatus, Status};
 use log::{debug, error, info, warn};
-use serde::Serialize;
 
-const SOCK_PATH: &str = "/tmp/fw-fanctrl-rs.sock";
+const POWER_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How often the heartbeat thread rechecks its interval, so a reload that
+/// turns heartbeats on or changes the interval applies within this long.
+const HEARTBEAT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Control connections served at once; more are turned away.
+const MAX_CONTROL_CLIENTS: usize = 16;
 
-const SOCK_INFO_PATH: &str = "/tmp/fw-fanctrl-info.sock";
+const LISTEN_RETRY_INITIAL: Duration = Duration::from_millis(500);
 
+const LISTEN_RETRY_MAX: Duration = Duration::from_secs(30);
+
+mod broadcast;
+mod control;
+mod fan_actuator;
 mod fan_config;
 mod fan_control;
+mod instance;
+mod power_supply;
+mod shutdown;
+mod systemd;
+mod temp_source;
+
+/// One controlled fan's output from a tick.
+struct FanTarget {
+    fan: Option<u32>,
+    duty: u8,
+    temperature: f32,
+    raw_speed: f32,
+}
 
-#[derive(Debug)]
-struct TempParsed {
-    f75303_local: Option<u32>,
-    f75303_cpu: Option<u32>,
-    f75303_ddr: Option<u32>,
-    apu: Option<u32>,
-    dgpu_vr: Option<u32>,
-    dgpu_vram: Option<u32>,
-    dgpu_amb: Option<u32>,
-    dgpu_temp: Option<u32>,
-    fan_speeds: Vec<u32>,
+impl FanTarget {
+    fn status(&self, index: u32, rpm: &[u32]) -> FanStatus {
+        FanStatus {
+            index,
+            target: self.duty,
+            rpm: rpm.get(index as usize).copied(),
+            temperature: Some(self.temperature),
+            raw_speed: Some(self.raw_speed),
+        }
+    }
 }
 
-#[derive(Serialize)]
-struct Status {
-    strategy: String,
-    speed: u8,
-    paused: bool,
+/// Pairs each controlled fan's target with its measured rpm. An untargeted
+/// duty is reported for every fan with an rpm reading and no target of its
+/// own.
+fn fan_statuses(targets: &[FanTarget], rpm: &[u32]) -> Vec<FanStatus> {
+    let own: Vec<u32> = targets.iter().filter_map(|target| target.fan).collect();
+    let mut statuses: Vec<FanStatus> = targets
+        .iter()
+        .filter_map(|target| Some(target.status(target.fan?, rpm)))
+        .collect();
+    if let Some(untargeted) = targets.iter().find(|target| target.fan.is_none()) {
+        statuses.extend(
+            (0..rpm.len().max(1) as u32)
+                .filter(|index| !own.contains(index))
+                .map(|index| untargeted.status(index, rpm)),
+        );
+        statuses.sort_by_key(|status| status.index);
+    }
+    statuses
 }
 
-fn parse_temp(input: &str) -> TempParsed {
-    let mut out = TempParsed {
-        f75303_local: None,
-        f75303_cpu: None,
-        f75303_ddr: None,
-        apu: None,
-        dgpu_vr: None,
-        dgpu_vram: None,
-        dgpu_amb: None,
-        dgpu_temp: None,
-        fan_speeds: Vec::new(),
+/// Runs the controller of every fan `strategy` drives and sends the
+/// resulting duties to `actuator`. A failed write doesn't stop the other
+/// fans from being set; the error is returned after all of them were tried.
+/// Controllers of fans the strategy no longer drives are dropped.
+fn drive_fans(
+    strategy: &Strategy,
+    readings: &TempReadings,
+    controllers: &mut BTreeMap<Option<u32>, FanController>,
+    critical: bool,
+    actuator: &mut dyn FanActuator,
+) -> (Vec<FanTarget>, std::io::Result<()>) {
+    // the actuator's own fans, not every fan the temperature source saw
+    let fan_count = if strategy.curves.iter().any(|curve| curve.fan.is_some()) {
+        actuator.read_rpm().map_or(0, |rpm| rpm.len())
+    } else {
+        0
     };
-
-    for line in input.lines() {
-        let line = line.trim();
-        if line.is_empty() {
-            continue;
+    let fans = strategy.fan_targets(fan_count, actuator.untargeted_covers_all());
+    controllers.retain(|fan, _| fans.contains(fan));
+
+    let mut targets = Vec::new();
+    let mut set_result = Ok(());
+    for fan in fans {
+        let ctrl = controllers
+            .entry(fan)
+            .or_insert_with(|| FanController::new(strategy));
+        let mut fan_speed_full = ctrl.update(readings, strategy, fan) as u8;
+        if critical {
+            fan_speed_full = 100;
+            ctrl.force(100.0);
         }
-
-        let to_val = |s: &str| s.parse::<u32>().ok();
-
-        if let Some(v) = line.strip_prefix("F75303_Local:") {
-            out.f75303_local = to_val(v.split_whitespace().next().unwrap_or(""));
-        } else if let Some(v) = line.strip_prefix("F75303_CPU:") {
-            out.f75303_cpu = to_val(v.split_whitespace().next().unwrap_or(""));
-        } else if let Some(v) = line.strip_prefix("F75303_DDR:") {
-            out.f75303_ddr = to_val(v.split_whitespace().next().unwrap_or(""));
-        } else if let Some(v) = line.strip_prefix("APU:") {
-            out.apu = to_val(v.split_whitespace().next().unwrap_or(""));
-        } else if let Some(v) = line.strip_prefix("dGPU VR:") {
-            out.dgpu_vr = to_val(v.split_whitespace().next().unwrap_or(""));
-        } else if let Some(v) = line.strip_prefix("dGPU VRAM:") {
-            out.dgpu_vram = to_val(v.split_whitespace().next().unwrap_or(""));
-        } else if let Some(v) = line.strip_prefix("dGPU AMB:") {
-            out.dgpu_amb = to_val(v.split_whitespace().next().unwrap_or(""));
-        } else if let Some(v) = line.strip_prefix("dGPU temp:") {
-            out.dgpu_temp = v.contains("NotPowered").then(|| 0).or_else(|| to_val(v));
-        } else if let Some(v) = line.strip_prefix("Fan Speed:") {
-            if let Some(num) = to_val(v.split_whitespace().next().unwrap_or("")) {
-                out.fan_speeds.push(num);
-            }
+        debug!("Fan {:?} speed: {}", fan, fan_speed_full);
+        if let Err(e) = actuator.set_duty(fan, fan_speed_full) {
+            error!("{} failed to set duty: {}", actuator.name(), e);
+            set_result = Err(e);
         }
+        targets.push(FanTarget {
+            fan,
+            duty: fan_speed_full,
+            temperature: ctrl.temperature(),
+            raw_speed: ctrl.raw_speed(),
+        });
     }
-
-    out
+    (targets, set_result)
 }
 
-lazy_static::lazy_static! {
-    static ref BROADCAST_LOCK: Mutex<()> = Mutex::new(());
+/// What the last fan update read, reported alongside the fan targets.
+#[derive(Clone, Default)]
+struct Telemetry {
+    temperature: Option<f32>,
+    sensors: BTreeMap<String, f32>,
+    timestamp: u64,
+    temperature_source: String,
+    fan_actuator: String,
 }
 
-fn broadcast_status(clients: &Arc<Mutex<Vec<UnixStream>>>, status: &Status) {
-    let _guard = BROADCAST_LOCK.lock().unwrap(); // only one broadcast at a time
-
-    if let Ok(msg) = serde_json::to_string(status) {
-        let mut clients_lock = clients.lock().unwrap();
-        clients_lock.retain(|mut client| {
-            if let Err(e) = client.write_all(format!("{}\n", msg).as_bytes()) {
-                eprintln!("Client disconnected: {}", e);
-                false
-            } else {
-                true
-            }
-        });
-    } else {
-        eprintln!("Failed to serialize status");
+impl Telemetry {
+    fn status(
+        &self,
+        strategy: &str,
+        speed: u8,
+        paused: bool,
+        fans: Vec<FanStatus>,
+        degraded: bool,
+    ) -> Status {
+        Status {
+            strategy: strategy.to_string(),
+            speed,
+            paused,
+            fans,
+            degraded,
+            temperature: self.temperature,
+            sensors: self.sensors.clone(),
+            timestamp: self.timestamp,
+            temperature_source: self.temperature_source.clone(),
+            fan_actuator: self.fan_actuator.clone(),
+        }
     }
 }
 
-fn run_daemon() -> std::io::Result<()> {
-    if Path::new(SOCK_PATH).exists() {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::AlreadyExists,
-            "daemon already running Socket already exsists?",
-        ));
-    }
+fn switch_strategy(
+    config: &FanConfig,
+    name: &str,
+    strategy_name: &Mutex<String>,
+    current_strategy: &Mutex<Strategy>,
+) -> bool {
+    let Some(strategy) = config.strategies.get(name) else {
+        return false;
+    };
+    *strategy_name.lock().unwrap() = name.to_string();
+    *current_strategy.lock().unwrap() = strategy.clone();
+    true
+}
 
-    if Path::new(SOCK_INFO_PATH).exists() {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::AlreadyExists,
-            "daemon already running Socket already exsists?",
-        ));
+fn reload_config(
+    config_path: &Path,
+    config: &Mutex<FanConfig>,
+    temp_source: &Mutex<Box<dyn TemperatureSource>>,
+    actuator: &Mutex<Box<dyn FanActuator>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let loaded = fan_config::load_or_create_config(config_path)?;
+    *temp_source.lock().unwrap() = temp_source::from_config(&loaded.temperature_source);
+    *actuator.lock().unwrap() = fan_actuator::from_config(&loaded.fan_actuator);
+    *config.lock().unwrap() = loaded;
+    Ok(())
+}
+
+/// Uses the socket systemd passed in for `path`, or binds it ourselves and
+/// records it in `owned` so it is removed on exit.
+fn open_listener(
+    path: &Path,
+    inherited: &mut Vec<UnixListener>,
+    owned: &mut Vec<PathBuf>,
+) -> std::io::Result<UnixListener> {
+    if let Some(listener) = systemd::take_listener(inherited, path) {
+        info!("Using socket-activated {}", path.display());
+        return Ok(listener);
     }
-    let (status_tx, status_rx) = mpsc::channel::<Status>();
-    let status_tx = Arc::new(status_tx);
-    let clients = Arc::new(Mutex::new(Vec::new()));
-    let info_listener = UnixListener::bind(SOCK_INFO_PATH)?;
-    fs::set_permissions(SOCK_INFO_PATH, fs::Permissions::from_mode(0o666))?;
-    let clients_clone = Arc::clone(&clients);
 
-    thread::spawn(move || {
-        for stream in info_listener.incoming() {
-            match stream {
-                Ok(stream) => {
-                    let mut clients_lock = clients_clone.lock().unwrap();
-                    clients_lock.push(stream);
-                }
-                Err(e) => eprintln!("Failed to accept client: {}", e),
-            }
+    instance::remove_stale_socket(path)?;
+    let listener = UnixListener::bind(path)?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o666))?;
+    owned.push(path.to_path_buf());
+    Ok(listener)
+}
+
+fn run_daemon(paths: &Paths) -> std::io::Result<()> {
+    // only root may create files next to the lock and sockets
+    for socket in [&paths.socket, &paths.info_socket] {
+        if let Some(dir) = socket.parent() {
+            fs::DirBuilder::new()
+                .recursive(true)
+                .mode(0o755)
+                .create(dir)?;
         }
-    });
+    }
+    let _instance_lock = instance::acquire(&paths.lock_file())?;
+    let mut notifier = systemd::Notifier::from_env();
+    let mut inherited = systemd::listen_fds();
+    let mut owned_sockets = Vec::new();
+    let info_listener = open_listener(&paths.info_socket, &mut inherited, &mut owned_sockets)?;
+    let listener = open_listener(&paths.socket, &mut inherited, &mut owned_sockets)?;
+    if !inherited.is_empty() {
+        warn!(
+            "Ignoring {} unexpected socket-activated fds",
+            inherited.len()
+        );
+    }
 
-    let clients_clone = Arc::clone(&clients);
+    let signals = shutdown::block_signals();
+    let config = fan_config::load_or_create_config(&paths.config).map_err(|e| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("invalid config {}: {}", paths.config.display(), e),
+        )
+    })?;
+    let config = Arc::new(Mutex::new(config));
+    let actuator = Arc::new(Mutex::new(fan_actuator::from_config(
+        &config.lock().unwrap().fan_actuator,
+    )));
+    let shutdown = Shutdown::new(Arc::clone(&actuator), Arc::clone(&config), owned_sockets);
+    shutdown.install_panic_hook();
+    let _shutdown_guard = shutdown.guard();
+
+    let (status_tx, status_rx) = mpsc::channel::<Event>();
+    let status_tx = Arc::new(status_tx);
+    let broadcaster = Arc::new(Broadcaster::new(&config.lock().unwrap().subscriber_queue));
+    let broadcaster_clone = Arc::clone(&broadcaster);
 
     thread::spawn(move || {
-        let clients_lock = clients_clone.lock().unwrap();
-        drop(clients_lock); // release lock initially
-
-        for status in status_rx {
-            if let Ok(msg) = serde_json::to_string(&status) {
-                let mut clients_lock = clients.lock().unwrap();
-                clients_lock.retain(|mut client| {
-                    if let Err(e) = client.write_all(format!("{}\n", msg).as_bytes()) {
-                        eprintln!("Client disconnected: {}", e);
-                        false // remove disconnected client
-                    } else {
-                        true
-                    }
-                });
-            } else {
-                eprintln!("Failed to serialize status");
+        for event in status_rx {
+            match serde_json::to_string(&event) {
+                Ok(msg) => broadcaster_clone.publish(&format!("{}\n", msg)),
+                Err(e) => error!("Failed to serialize event: {}", e),
             }
         }
     });
 
-    let listener = UnixListener::bind(SOCK_PATH)?;
-    fs::set_permissions(SOCK_PATH, fs::Permissions::from_mode(0o666))?;
-
-    let mut config = fan_config::load_or_create_config().unwrap();
-    let strategy_name = Arc::new(Mutex::new(config.default_strategy.clone()));
-    let current_strategy = Arc::new(Mutex::new(Strategy {
-        fan_speed_update_frequency: 2.0,
-        moving_average_interval: 30,
-        speed_curve: vec![],
-    }));
+    let strategy_name = Arc::new(Mutex::new(String::new()));
+    let current_strategy = Arc::new(Mutex::new(Strategy::default()));
 
     {
-        let name = strategy_name.lock().unwrap();
-        let mut profile = current_strategy.lock().unwrap();
-        *profile = config
-            .strategies
-            .get(&*name)
-            .expect("Missing default")
-            .clone();
+        let config = config.lock().unwrap();
+        if !switch_strategy(
+            &config,
+            &config.default_strategy,
+            &strategy_name,
+            &current_strategy,
+        ) {
+            panic!("Missing default");
+        }
     }
-    let controller = {
-        let profile = current_strategy.lock().unwrap(); // lock temporarily
-        FanController::new(&profile) // use profile
-    }; // lock is automatically dropped here
-    let controller = Arc::new(Mutex::new(controller));
+    let mut controllers: BTreeMap<Option<u32>, FanController> = BTreeMap::new();
+    let mut controllers_strategy = String::new();
+    let temp_source = Arc::new(Mutex::new(temp_source::from_config(
+        &config.lock().unwrap().temperature_source,
+    )));
+    let temp_source_fan = Arc::clone(&temp_source);
+    let actuator_fan = Arc::clone(&actuator);
     let profile_fan_clone = Arc::clone(&current_strategy);
     let strategy_name_clone = Arc::clone(&strategy_name);
-    let controller_clone = Arc::clone(&controller);
     let paused = Arc::new(Mutex::new(false));
     let paused_thread = paused.clone();
     let fan_speed_shared = Arc::new(Mutex::new(0u8));
     let fan_speed_thread = Arc::clone(&fan_speed_shared);
-    let mut last_strategy = String::new();
-    let mut last_speed = 0;
-    let mut last_active = false;
+    let fans_shared = Arc::new(Mutex::new(Vec::<FanStatus>::new()));
+    let fans_thread = Arc::clone(&fans_shared);
+    let telemetry_shared = Arc::new(Mutex::new(Telemetry::default()));
+    let telemetry_thread = Arc::clone(&telemetry_shared);
+    let degraded_shared = Arc::new(Mutex::new(false));
+    let degraded_thread = Arc::clone(&degraded_shared);
+    let mut last_status: Option<Status> = None;
 
     let status_tx_fan = Arc::clone(&status_tx);
+    let fan_config = Arc::clone(&config);
+    let mut critical_override = CriticalOverride::default();
+    let mut failures = 0u32;
 
     let fan_thread = thread::spawn(move || loop {
+        notifier.watchdog();
         {
             info!("checking changes");
             let name_lock = strategy_name_clone.lock().unwrap();
             let fan_speed = fan_speed_thread.lock().unwrap();
             let paused = paused_thread.lock().unwrap();
-
-            if &last_strategy != &*name_lock || last_speed != *fan_speed || last_active != *paused {
+            let fans = fans_thread.lock().unwrap();
+            let degraded = *degraded_thread.lock().unwrap();
+            let status = telemetry_thread.lock().unwrap().status(
+                &name_lock,
+                *fan_speed,
+                *paused,
+                fans.clone(),
+                degraded,
+            );
+
+            // the timestamp moves on every tick, anything else is news
+            let unstamped = Status {
+                timestamp: 0,
+                ..status.clone()
+            };
+            if last_status.as_ref() != Some(&unstamped) {
                 info!("changes detected writing to socket");
-                let status = Status {
-                    strategy: (name_lock).to_string(),
-                    speed: *fan_speed,
-                    paused: *paused,
-                };
-
-                let _ = status_tx_fan.send(status);
-
-                last_strategy = name_lock.clone();
-                last_speed = *fan_speed;
-                last_active = *paused;
+                let _ = status_tx_fan.send(Event::Status(status));
+                last_status = Some(unstamped);
             }
         }
 
-        let sleep_time;
+        let mut sleep_time;
         {
             let is_paused = paused_thread.lock().unwrap();
             if *is_paused {
                 drop(is_paused);
-                thread::park();
+                match notifier.watchdog_interval() {
+                    Some(interval) => thread::park_timeout(interval),
+                    None => thread::park(),
+                }
                 continue;
             }
         }
 
         {
+            let (global_critical, policy) = {
+                let config = fan_config.lock().unwrap();
+                (config.critical_temp.clone(), config.failure_policy.clone())
+            };
             let profile = profile_fan_clone.lock().unwrap();
             let name = strategy_name_clone.lock().unwrap();
             sleep_time = profile.fan_speed_update_frequency;
@@ -249,164 +359,260 @@ fn run_daemon() -> std::io::Result<()> {
             debug!("Update freq: {}", profile.fan_speed_update_frequency);
             debug!("Strategy: {}", *name);
 
-            let temp = Command::new("framework_tool")
-                .arg("--thermal")
-                .output()
-                .expect("framework_tool failed");
-
-            let stdout = String::from_utf8_lossy(&temp.stdout);
-            let parsed = parse_temp(&stdout);
-            debug!("{:?}", parsed);
-            let temperature: f32;
-            if parsed.apu > parsed.dgpu_temp {
-                temperature = parsed.apu.map(|v| v as f32).unwrap_or(0.0);
-            } else {
-                temperature = parsed.dgpu_temp.map(|v| v as f32).unwrap_or(0.0);
-            }
-            debug!("temp: {:?}", temperature);
-            let fan_speed = {
-                let mut ctrl = controller_clone.lock().unwrap();
-                ctrl.update(temperature, &profile)
+            let (readings, temperature_source) = {
+                let mut source = temp_source_fan.lock().unwrap();
+                debug!("Temperature source: {}", source.name());
+                let readings = source
+                    .read()
+                    .and_then(|readings| readings.check(&profile).map(|_| readings));
+                (readings, source.name())
             };
-            let fan_speed_full: u8 = fan_speed as u8;
-            {
-                let mut fan_speed_lock = fan_speed_thread.lock().unwrap();
-                *fan_speed_lock = fan_speed_full;
+            let mut actuator = actuator_fan.lock().unwrap();
+
+            let result = readings.and_then(|readings| {
+                debug!("{:?}", readings.sensors);
+
+                let critical = profile.critical_temp.as_ref().or(global_critical.as_ref());
+                let hottest = profile
+                    .sensor_names()
+                    .filter_map(|sensor| readings.get(sensor))
+                    .reduce(f32::max);
+                if let Some(temperature) = hottest {
+                    if let Some(active) = critical_override.update(temperature, critical) {
+                        if active {
+                            warn!("Critical temperature {}, forcing fans to 100%", temperature);
+                        } else {
+                            info!(
+                                "Temperature back to {}, critical override released",
+                                temperature
+                            );
+                        }
+                        let _ = status_tx_fan.send(Event::CriticalTemp {
+                            active,
+                            temperature,
+                        });
+                    }
+                }
+
+                let rpm = if readings.fan_speeds.is_empty() {
+                    actuator.read_rpm().unwrap_or_default()
+                } else {
+                    readings.fan_speeds.clone()
+                };
+                debug!("Fan RPM: {:?}", rpm);
+
+                // smoothing, hysteresis and PID state belong to one strategy
+                if controllers_strategy != *name {
+                    controllers.clear();
+                    controllers_strategy = name.clone();
+                }
+                let (targets, set_result) = drive_fans(
+                    &profile,
+                    &readings,
+                    &mut controllers,
+                    critical_override.active(),
+                    actuator.as_mut(),
+                );
+                {
+                    let mut fan_speed_lock = fan_speed_thread.lock().unwrap();
+                    *fan_speed_lock = targets.iter().map(|t| t.duty).max().unwrap_or(0);
+                }
+                *fans_thread.lock().unwrap() = fan_statuses(&targets, &rpm);
+                *telemetry_thread.lock().unwrap() = Telemetry {
+                    temperature: targets.iter().map(|t| t.temperature).reduce(f32::max),
+                    sensors: readings.sensors,
+                    timestamp: SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map_or(0, |d| d.as_millis() as u64),
+                    temperature_source: temperature_source.to_string(),
+                    fan_actuator: actuator.name().to_string(),
+                };
+                set_result
+            });
+
+            match result {
+                Ok(()) => {
+                    if failures >= policy.max_failures {
+                        info!("Fan control recovered after {} failures", failures);
+                    }
+                    failures = 0;
+                    *degraded_thread.lock().unwrap() = false;
+                    notifier.ready();
+                }
+                Err(e) => {
+                    failures += 1;
+                    warn!("Fan update failed ({} in a row): {}", failures, e);
+                    sleep_time = policy.retry_delay(failures);
+
+                    if failures >= policy.max_failures {
+                        if failures == policy.max_failures {
+                            error!("Giving up after {} failures, using failsafe", failures);
+                        }
+                        let applied = match policy.failsafe {
+                            Failsafe::AutoControl => actuator.restore_auto(),
+                            Failsafe::FixedDuty { duty } => actuator.set_duty(None, duty),
+                            Failsafe::FixedRpm { rpm } => actuator.set_rpm(None, rpm),
+                        };
+                        if let Err(e) = applied {
+                            error!("{} failed to apply failsafe: {}", actuator.name(), e);
+                        }
+                        *degraded_thread.lock().unwrap() = true;
+                    }
+                }
             }
-            debug!("Fan speed: {}", fan_speed_full);
-            let output = Command::new("framework_tool")
-                .arg("--fansetduty")
-                .arg(fan_speed_full.to_string())
-                .output()
-                .expect("framework_tool failed");
-            let stderr = str::from_utf8(&output.stderr).unwrap_or("<invalid utf8>");
-            debug!("stderr: {}", stderr);
         }
 
-        thread::sleep(Duration::from_secs_f32(sleep_time));
+        notifier.sleep(Duration::from_secs_f32(sleep_time));
     });
 
-    let status_tx_listener = Arc::clone(&status_tx);
-    loop {
-        let paused_listener = paused.clone();
+    let power_config = Arc::clone(&config);
+    let power_strategy_name = Arc::clone(&strategy_name);
+    let power_strategy = Arc::clone(&current_strategy);
+    let status_tx_power = Arc::clone(&status_tx);
 
-        if let Ok((mut stream, _)) = listener.accept() {
-            let mut buf = [0u8; 1024];
+    thread::spawn(move || {
+        let mut power_switch = PowerSwitch::default();
+
+        loop {
+            let root = power_config.lock().unwrap().power_supply_root.clone();
+            match power_supply::on_battery(Path::new(&root)) {
+                Ok(on_battery) => {
+                    let config = power_config.lock().unwrap();
+                    let current = power_strategy_name.lock().unwrap().clone();
+                    if let Some(switch_to) =
+                        power_switch.update(on_battery, &current, &config.strategy_on_discharging)
+                    {
+                        if let Some(name) = switch_to {
+                            if switch_strategy(
+                                &config,
+                                &name,
+                                &power_strategy_name,
+                                &power_strategy,
+                            ) {
+                                info!("Switched to strategy: {}", name);
+                            } else {
+                                warn!("Unknown strategy: {}", name);
+                            }
+                        }
+
+                        let strategy = power_strategy_name.lock().unwrap().clone();
+                        info!("On battery: {}, strategy in use: {}", on_battery, strategy);
+                        let _ = status_tx_power.send(Event::PowerSource {
+                            on_battery,
+                            strategy,
+                        });
+                    }
+                }
+                Err(e) => debug!("Failed to read {}: {}", root, e),
+            }
 
-            if let Ok(n) = stream.read(&mut buf) {
-                let received = String::from_utf8_lossy(&buf[..n]).to_string();
-                let received_trimmed = received.trim();
+            thread::sleep(POWER_POLL_INTERVAL);
+        }
+    });
 
-                if let Some(name) = received_trimmed.strip_prefix("use ") {
-                    let name = name.trim();
-                    info!("received: {}", received_trimmed);
+    let signal_config = Arc::clone(&config);
+    let signal_temp_source = Arc::clone(&temp_source);
+    let signal_actuator = Arc::clone(&actuator);
+    let signal_config_path = paths.config.clone();
+    shutdown::spawn_signal_thread(signals, shutdown, move || {
+        if let Err(e) = reload_config(
+            &signal_config_path,
+            &signal_config,
+            &signal_temp_source,
+            &signal_actuator,
+        ) {
+            error!("Failed to reload config: {}", e);
+        }
+    });
 
-                    if config.strategies.contains_key(name) {
-                        {
-                            let mut name_lock = strategy_name.lock().unwrap();
-                            *name_lock = name.to_string();
-                        }
-                        {
-                            let mut profile = current_strategy.lock().unwrap();
-                            *profile = config.strategies[name].clone();
-                        }
+    let control = Control {
+        config_path: paths.config.clone(),
+        config,
+        strategy_name,
+        current_strategy,
+        paused,
+        fan_speed: fan_speed_shared,
+        fans: fans_shared,
+        telemetry: telemetry_shared,
+        degraded: degraded_shared,
+        temp_source,
+        actuator,
+        fan_thread: fan_thread.thread().clone(),
+    };
 
-                        info!("Switched to strategy: {}", name);
-                        let msg = format!("Switched to strategy: {}", name);
-                        stream.write_all(msg.as_bytes())?;
-                    } else {
-                        warn!("Unknown strategy: {}", name);
-                        let msg = format!("Unknown strategy: {}", name);
-                        stream.write_all(msg.as_bytes())?;
+    let info_control = control.clone();
+    thread::spawn(move || {
+        for stream in info_listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let subscribed = broadcaster.subscribe(stream, || {
+                        let snapshot = Event::Status(info_control.status());
+                        format!("{}\n", serde_json::to_string(&snapshot).unwrap())
+                    });
+                    if let Err(e) = subscribed {
+                        warn!("Failed to subscribe info client: {}", e);
                     }
                 }
-                if received_trimmed == "print" {
-                    let name_lock = strategy_name.lock().unwrap();
-                    let fan_speed = fan_speed_shared.lock().unwrap();
-                    let paused = paused_listener.lock().unwrap();
-
-                    let status = Status {
-                        strategy: (name_lock).to_string(),
-                        speed: *fan_speed,
-                        paused: *paused,
-                    };
-
-                    let msg = serde_json::to_string(&status).unwrap();
-
-                    stream.write_all(msg.as_bytes())?;
-                } else if let Some(arguments) = received_trimmed.strip_prefix("print ") {
-                    let name_lock = strategy_name.lock().unwrap();
-                    let fan_speed = fan_speed_shared.lock().unwrap();
-                    let paused = paused_listener.lock().unwrap();
-
-                    let status = Status {
-                        strategy: (name_lock).to_string(),
-                        speed: *fan_speed,
-                        paused: *paused,
-                    };
-
-                    let msg = if arguments.trim() == "json" {
-                        serde_json::to_string(&status).unwrap()
-                    } else {
-                        format!(
-                            "Strategy: {}\nSpeed: {}\nActive: {}",
-                            status.strategy, status.speed, status.paused
-                        )
-                    };
-
-                    stream.write_all(msg.as_bytes())?;
-                } else if let Some(arguments) = received_trimmed.strip_prefix("tool ") {
-                    let mut cmd = Command::new("framework_tool");
-                    for arg in arguments.split_whitespace() {
-                        cmd.arg(arg);
-                    }
-                    let output = cmd.output().expect("framework_tool failed");
-                    let stderr = std::str::from_utf8(&output.stderr).unwrap_or("<invalid utf8>");
-                    stream.write_all(stderr.as_bytes())?;
-                } else if received_trimmed == "reset" {
-                    let mut profile = current_strategy.lock().unwrap();
-                    *profile = config.strategies[&config.default_strategy.clone()].clone();
-                    let msg = format!(
-                        "Strategy reset to default! Strategy in use: {}",
-                        config.default_strategy
-                    );
-                    stream.write_all(msg.as_bytes())?;
-                } else if received_trimmed == "pause" {
-                    Command::new("framework_tool")
-                        .arg("--autofanctrl")
-                        .output()
-                        .expect("framework_tool failed");
-                    let mut pause = paused_listener.lock().unwrap();
-                    *pause = true;
-                    stream.write_all(b"Service paused!")?;
-                } else if received_trimmed == "resume" {
-                    let mut pause = paused_listener.lock().unwrap();
-                    *pause = false;
-                    fan_thread.thread().unpark();
-                    stream.write_all(b"Service resumed!")?;
-                } else if received_trimmed == "reload" {
-                    config = fan_config::load_or_create_config().unwrap();
-                    stream.write_all(b"Config reloaded")?;
-                } else {
-                    stream.write_all(b"unknown or unfinished argument")?;
+                Err(e) => warn!("Failed to accept info client: {}", e),
+            }
+        }
+    });
+
+    let heartbeat_control = control.clone();
+    let heartbeat_tx = Arc::clone(&status_tx);
+    thread::spawn(move || {
+        let mut last_sent = Instant::now();
+        loop {
+            let heartbeat = heartbeat_control.config.lock().unwrap().heartbeat_secs;
+            let Some(secs) = heartbeat.filter(|secs| *secs > 0.0) else {
+                last_sent = Instant::now();
+                thread::sleep(HEARTBEAT_POLL_INTERVAL);
+                continue;
+            };
+
+            // sleep in short steps so a reload that shortens the interval
+            // takes effect without waiting out the old one
+            let remaining = Duration::from_secs_f32(secs).saturating_sub(last_sent.elapsed());
+            if !remaining.is_zero() {
+                thread::sleep(remaining.min(HEARTBEAT_POLL_INTERVAL));
+                continue;
+            }
+            let status = heartbeat_control.status();
+            let _ = heartbeat_tx.send(Event::Status(status));
+            last_sent = Instant::now();
+        }
+    });
+
+    let clients = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                if clients.fetch_add(1, Ordering::SeqCst) >= MAX_CONTROL_CLIENTS {
+                    clients.fetch_sub(1, Ordering::SeqCst);
+                    warn!("Too many control clients, refusing a connection");
+                    continue;
                 }
+                let control = control.clone();
+                let clients = Arc::clone(&clients);
+                thread::spawn(move || {
+                    if let Err(e) = control.serve(stream) {
+                        debug!("Control client error: {}", e);
+                    }
+                    clients.fetch_sub(1, Ordering::SeqCst);
+                });
             }
+            Err(e) => warn!("Failed to accept control client: {}", e),
         }
     }
-}
 
-fn send_to_daemon(msg: String) -> std::io::Result<String> {
-    let mut stream = UnixStream::connect(SOCK_PATH)?;
-    stream.write_all(msg.as_bytes())?;
-
-    let mut buf = [0u8; 1024];
-    let n = stream.read(&mut buf)?;
-    Ok(String::from_utf8_lossy(&buf[..n]).to_string())
+    Ok(())
 }
 
 fn print_help() {
     println!(
-        "Usage:
+        "Usage: fw-fanctrl-rs [options] <command>
+
+Commands:
     run             Start the fan control daemon (requires root)
     use <strategy>  Switch to a fan strategy
     print <format>  Show current strategy, fan speed, and status (format can be json or human)
@@ -416,52 +622,226 @@ fn print_help() {
     reload          Reload config
     listen          listen for changes like fan speed strategy paused
     tool <args>     Run arbitrary framework_tool commands
-    help / --help   Show this help message"
+    help / --help   Show this help message
+
+Options:
+    --socket <path>       Control socket (FW_FANCTRL_SOCKET, default /run/fw-fanctrl-rs/fw-fanctrl-rs.sock)
+    --info-socket <path>  Info socket (FW_FANCTRL_INFO_SOCKET, default /run/fw-fanctrl-rs/fw-fanctrl-info.sock)
+    --config <path>       Config file (FW_FANCTRL_CONFIG, default /etc/fw-fanctrl-rs/config.ron)"
     );
 }
 
-fn listen_socket() -> std::io::Result<()> {
-    let mut stream = UnixStream::connect(SOCK_INFO_PATH)?;
-    let mut buffer = [0u8; 1024];
-
+/// Prints every event, reconnecting with backoff whenever the daemon goes
+/// away. Each connection starts with a full status snapshot.
+fn listen_socket(info_socket: &Path) {
+    let mut retry = LISTEN_RETRY_INITIAL;
     loop {
-        match stream.read(&mut buffer) {
-            Ok(0) => break, // connection closed
-            Ok(n) => {
-                let msg = String::from_utf8_lossy(&buffer[..n]);
-                println!("{}", msg); // only prints when a message is received
+        match client::subscribe(info_socket) {
+            Ok(events) => {
+                retry = LISTEN_RETRY_INITIAL;
+                for event in events {
+                    if let Ok(msg) = serde_json::to_string(&event) {
+                        println!("{}", msg);
+                    }
+                }
+                warn!("Daemon closed the info socket, reconnecting");
             }
-            Err(e) => {
-                eprintln!("Read error: {}", e);
-                break;
+            Err(e) if retry == LISTEN_RETRY_INITIAL => {
+                warn!("Failed to connect to {}: {}", info_socket.display(), e)
             }
+            Err(e) => debug!("Failed to connect to {}: {}", info_socket.display(), e),
         }
+        thread::sleep(retry);
+        retry = (retry * 2).min(LISTEN_RETRY_MAX);
     }
-
-    Ok(())
 }
 
 fn main() {
     env_logger::init();
-    let args: Vec<String> = std::env::args().collect();
+    let (paths, args) = match Paths::from_args(std::env::args().skip(1).collect()) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(2);
+        }
+    };
 
-    if args.len() > 1 && (args[1] == "--help" || args[1] == "help") {
+    if !args.is_empty() && (args[0] == "--help" || args[0] == "help") {
         print_help();
-    } else if args.len() > 1 && args[1] == "run" {
+    } else if !args.is_empty() && args[0] == "run" {
         if unsafe { libc::geteuid() != 0 } {
             error!("Root privileges required.");
             std::process::exit(1);
         }
-        if let Err(e) = run_daemon() {
+        if let Err(e) = run_daemon(&paths) {
             error!("failed: {}", e);
+            std::process::exit(1);
         }
-    } else if args.len() > 1 && args[1] == "listen" {
-        listen_socket().unwrap();
-    } else if args.len() > 1 {
-        let msg = args[1..].join(" ");
-        match send_to_daemon(msg) {
-            Ok(response) => println!("{}", response),
+    } else if !args.is_empty() && args[0] == "listen" {
+        listen_socket(&paths.info_socket);
+    } else if !args.is_empty() {
+        let Some(command) = TextCommand::parse(&args.join(" ")) else {
+            println!("unknown or unfinished argument");
+            std::process::exit(1);
+        };
+        match Client::connect(&paths).and_then(|mut client| client.call(&command.method)) {
+            Ok(value) => println!("{}", command.render(&Ok(value))),
+            Err(client::Error::Rpc(e)) => {
+                println!("{}", command.render::<serde_json::Value>(&Err(e)));
+                std::process::exit(1);
+            }
             Err(e) => error!("failed: {}", e),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fan_config::{
+        FanActuatorConfig, SensorCurve, SensorInput, SpeedPoint, TemperatureSourceConfig,
+    };
+
+    fn linear(from: f32, to: f32) -> Vec<SpeedPoint> {
+        vec![
+            SpeedPoint {
+                temp: from,
+                speed: 0.0,
+            },
+            SpeedPoint {
+                temp: to,
+                speed: 100.0,
+            },
+        ]
+    }
+
+    /// One tick with the mock backends. The mock fans report an rpm equal to
+    /// their duty, so the returned rpm are the duties the actuator received.
+    fn tick(strategy: &Strategy, critical: bool) -> (Vec<FanTarget>, Vec<u32>) {
+        tick_with(strategy, &mut BTreeMap::new(), critical)
+    }
+
+    fn tick_with(
+        strategy: &Strategy,
+        controllers: &mut BTreeMap<Option<u32>, FanController>,
+        critical: bool,
+    ) -> (Vec<FanTarget>, Vec<u32>) {
+        let mut source = temp_source::from_config(&TemperatureSourceConfig::Mock {
+            sensors: [("APU".to_string(), 50.0), ("dGPU".to_string(), 70.0)].into(),
+            fan_speeds: vec![],
+        });
+        let mut actuator = fan_actuator::from_config(&FanActuatorConfig::Mock {
+            max_rpm: 100,
+            fans: 2,
+        });
+
+        let readings = source.read().unwrap();
+        let (targets, result) = drive_fans(
+            strategy,
+            &readings,
+            controllers,
+            critical,
+            actuator.as_mut(),
+        );
+        result.unwrap();
+        (targets, actuator.read_rpm().unwrap())
+    }
+
+    #[test]
+    fn mock_tick_sets_curve_duty() {
+        let strategy = Strategy {
+            moving_average_interval: 1,
+            speed_curve: linear(40.0, 60.0),
+            sensors: vec![SensorInput::new("APU")],
+            ..Default::default()
+        };
+
+        let (targets, duties) = tick(&strategy, false);
+        assert_eq!(duties, vec![50, 50]);
+        assert_eq!(targets[0].temperature, 50.0);
+
+        let (_, duties) = tick(&strategy, true);
+        assert_eq!(duties, vec![100, 100]);
+    }
+
+    fn curve(sensor: &str, fan: Option<u32>, from: f32, to: f32) -> SensorCurve {
+        SensorCurve {
+            sensor: sensor.to_string(),
+            offset: 0.0,
+            fan,
+            speed_curve: linear(from, to),
+        }
+    }
+
+    fn curves(curves: Vec<SensorCurve>) -> Strategy {
+        Strategy {
+            moving_average_interval: 1,
+            curves,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn mock_tick_sets_per_fan_duty() {
+        let strategy = curves(vec![
+            curve("APU", Some(0), 40.0, 60.0),
+            curve("dGPU", Some(1), 60.0, 80.0),
+        ]);
+        let (_, duties) = tick(&strategy, false);
+        assert_eq!(duties, vec![50, 50]);
+    }
+
+    #[test]
+    fn fans_without_a_curve_are_still_driven() {
+        // fan 1 follows the untargeted curve
+        let strategy = curves(vec![
+            curve("APU", Some(0), 40.0, 60.0),
+            curve("dGPU", None, 65.0, 75.0),
+        ]);
+        let (targets, duties) = tick(&strategy, false);
+        assert_eq!(duties, vec![50, 50]);
+        assert_eq!(targets.len(), 2);
+
+        // and with no untargeted curve, the hottest curve of all
+        let strategy = curves(vec![curve("APU", Some(0), 45.0, 55.0)]);
+        let (_, duties) = tick(&strategy, false);
+        assert_eq!(duties, vec![50, 50]);
+    }
+
+    #[test]
+    fn untargeted_duty_is_reported_for_fans_without_their_own() {
+        let target = |fan, duty| FanTarget {
+            fan,
+            duty,
+            temperature: 50.0,
+            raw_speed: duty as f32,
+        };
+        let statuses = fan_statuses(&[target(None, 30), target(Some(1), 60)], &[300, 600, 310]);
+        let duties: Vec<_> = statuses
+            .iter()
+            .map(|s| (s.index, s.target, s.rpm))
+            .collect();
+        assert_eq!(
+            duties,
+            vec![(0, 30, Some(300)), (1, 60, Some(600)), (2, 30, Some(310))]
+        );
+    }
+
+    #[test]
+    fn controllers_of_dropped_fans_are_removed() {
+        let mut controllers = BTreeMap::new();
+        tick_with(
+            &curves(vec![curve("APU", Some(1), 40.0, 60.0)]),
+            &mut controllers,
+            false,
+        );
+        assert_eq!(controllers.len(), 2);
+
+        tick_with(
+            &curves(vec![curve("APU", None, 40.0, 60.0)]),
+            &mut controllers,
+            false,
+        );
+        assert_eq!(controllers.keys().collect::<Vec<_>>(), vec![&None]);
+    }
+}
@@ -0,0 +1,83 @@
+use std::path::{Path, PathBuf};
+
+const RUN_DIR: &str = "/run/fw-fanctrl-rs";
+
+const CONFIG_PATH: &str = "/etc/fw-fanctrl-rs/config.ron";
+
+/// Where the daemon and the client find each other and the config. Each
+/// path comes from its flag, then its `FW_FANCTRL_*` variable, then the
+/// default.
+#[derive(Debug, Clone)]
+pub struct Paths {
+    pub socket: PathBuf,
+    pub info_socket: PathBuf,
+    pub config: PathBuf,
+}
+
+impl Paths {
+    /// Strips the leading `--socket`, `--info-socket` and `--config` options
+    /// (either `--flag value` or `--flag=value`) off `args` and returns the
+    /// remaining command.
+    pub fn from_args(mut args: Vec<String>) -> Result<(Self, Vec<String>), String> {
+        let mut socket = None;
+        let mut info_socket = None;
+        let mut config = None;
+
+        while let Some(arg) = args.first() {
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+                None => (arg.clone(), None),
+            };
+            let slot = match flag.as_str() {
+                "--socket" => &mut socket,
+                "--info-socket" => &mut info_socket,
+                "--config" => &mut config,
+                _ => break,
+            };
+            args.remove(0);
+            let value = match inline {
+                Some(value) => value,
+                None if !args.is_empty() => args.remove(0),
+                None => return Err(format!("{} requires a path", flag)),
+            };
+            *slot = Some(PathBuf::from(value));
+        }
+
+        Ok((Self::with_overrides(socket, info_socket, config), args))
+    }
+
+    /// The paths to use without any flags, e.g. from a client library user.
+    pub fn from_env() -> Self {
+        Self::with_overrides(None, None, None)
+    }
+
+    fn with_overrides(
+        socket: Option<PathBuf>,
+        info_socket: Option<PathBuf>,
+        config: Option<PathBuf>,
+    ) -> Self {
+        Self {
+            socket: resolve(socket, "FW_FANCTRL_SOCKET", || {
+                Path::new(RUN_DIR).join("fw-fanctrl-rs.sock")
+            }),
+            info_socket: resolve(info_socket, "FW_FANCTRL_INFO_SOCKET", || {
+                Path::new(RUN_DIR).join("fw-fanctrl-info.sock")
+            }),
+            config: resolve(config, "FW_FANCTRL_CONFIG", || PathBuf::from(CONFIG_PATH)),
+        }
+    }
+
+    /// Lives next to the control socket so isolated daemons don't share it.
+    pub fn lock_file(&self) -> PathBuf {
+        self.socket.with_extension("pid")
+    }
+}
+
+fn resolve(flag: Option<PathBuf>, var: &str, default: impl FnOnce() -> PathBuf) -> PathBuf {
+    flag.or_else(|| {
+        std::env::var_os(var)
+            .filter(|v| !v.is_empty())
+            .map(PathBuf::from)
+    })
+    .unwrap_or_else(default)
+}
@@ -0,0 +1,137 @@
+use std::fs;
+use std::path::Path;
+
+/// Whether the machine runs on battery, judged from `<root>/*/online` of the
+/// AC adapters and `<root>/*/status` of the batteries.
+///
+/// Any online adapter means AC. Without one, a discharging battery means
+/// battery; machines without batteries never report being on battery.
+pub fn on_battery(root: &Path) -> std::io::Result<bool> {
+    let mut discharging = false;
+
+    for entry in fs::read_dir(root)? {
+        let supply = entry?.path();
+        let read = |attr: &str| {
+            fs::read_to_string(supply.join(attr))
+                .map(|v| v.trim().to_string())
+                .unwrap_or_default()
+        };
+
+        match read("type").as_str() {
+            "Battery" => discharging |= read("status") == "Discharging",
+            _ => {
+                if read("online") == "1" {
+                    return Ok(false);
+                }
+            }
+        }
+    }
+
+    Ok(discharging)
+}
+
+/// Follows the power source and decides the strategy switches that go with
+/// it: `strategy_on_discharging` when going on battery, and back to the
+/// strategy from before when AC returns, unless the user switched meanwhile.
+#[derive(Default)]
+pub struct PowerSwitch {
+    on_battery: bool,
+    strategy_before_battery: Option<String>,
+}
+
+impl PowerSwitch {
+    /// `None` while the power source stays the same. On a change, the
+    /// strategy to switch to, if any.
+    pub fn update(
+        &mut self,
+        on_battery: bool,
+        current: &str,
+        discharging: &str,
+    ) -> Option<Option<String>> {
+        if on_battery == self.on_battery {
+            return None;
+        }
+        self.on_battery = on_battery;
+
+        if !on_battery {
+            // only undo our own switch, not one the user made meanwhile
+            return Some(
+                self.strategy_before_battery
+                    .take()
+                    .filter(|_| current == discharging),
+            );
+        }
+        if discharging.is_empty() {
+            return Some(None);
+        }
+        self.strategy_before_battery = Some(current.to_string());
+        Some(Some(discharging.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A `power_supply` tree with one AC adapter and one battery.
+    fn fake_supplies(online: &str, status: &str) -> tempfile::TempDir {
+        let root = tempfile::tempdir().unwrap();
+        for (supply, attrs) in [
+            ("ACAD", [("type", "Mains"), ("online", online)]),
+            ("BAT1", [("type", "Battery"), ("status", status)]),
+        ] {
+            let supply = root.path().join(supply);
+            fs::create_dir(&supply).unwrap();
+            for (attr, value) in attrs {
+                fs::write(supply.join(attr), format!("{}\n", value)).unwrap();
+            }
+        }
+        root
+    }
+
+    #[test]
+    fn reads_power_source() {
+        for (online, status, expected) in [
+            ("1", "Charging", false),
+            ("1", "Discharging", false),
+            ("0", "Discharging", true),
+            ("0", "Full", false),
+        ] {
+            let root = fake_supplies(online, status);
+            assert_eq!(
+                on_battery(root.path()).unwrap(),
+                expected,
+                "{} {}",
+                online,
+                status
+            );
+        }
+        assert!(on_battery(Path::new("/nonexistent/power_supply")).is_err());
+    }
+
+    #[test]
+    fn switches_to_battery_strategy_and_back() {
+        let mut power = PowerSwitch::default();
+        assert_eq!(power.update(false, "medium", "lazy"), None);
+        assert_eq!(
+            power.update(true, "medium", "lazy"),
+            Some(Some("lazy".to_string()))
+        );
+        assert_eq!(power.update(true, "lazy", "lazy"), None);
+        assert_eq!(
+            power.update(false, "lazy", "lazy"),
+            Some(Some("medium".to_string()))
+        );
+    }
+
+    #[test]
+    fn keeps_a_switch_made_on_battery() {
+        let mut power = PowerSwitch::default();
+        power.update(true, "medium", "lazy");
+        assert_eq!(power.update(false, "agile", "lazy"), Some(None));
+
+        // and nothing is restored on the next trip either
+        power.update(true, "agile", "");
+        assert_eq!(power.update(false, "agile", ""), Some(None));
+    }
+}
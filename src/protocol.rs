@@ -0,0 +1,292 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::Status;
+
+/// Bumped on incompatible changes to requests or replies.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Control socket requests, one JSON object per line:
+/// `{"id":1,"method":"use","params":{"strategy":"lazy"}}`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Request {
+    #[serde(default)]
+    pub id: Option<u64>,
+    #[serde(flatten)]
+    pub method: Method,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "method", content = "params", rename_all = "snake_case")]
+pub enum Method {
+    /// Optional first request; the daemon refuses versions it doesn't speak.
+    Hello {
+        version: u32,
+    },
+    Use {
+        strategy: String,
+    },
+    Status,
+    Reset,
+    Pause,
+    Resume,
+    Reload,
+    Tool {
+        args: Vec<String>,
+    },
+}
+
+impl Method {
+    const NAMES: [&'static str; 8] = [
+        "hello", "use", "status", "reset", "pause", "resume", "reload", "tool",
+    ];
+}
+
+/// Either `{"id":1,"result":...}` or `{"id":1,"error":{"code":...}}`. The
+/// daemon fills in a typed result, clients read it back as a `Value`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Response<T = Value> {
+    pub id: Option<u64>,
+    #[serde(flatten)]
+    pub outcome: Outcome<T>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome<T = Value> {
+    Result(T),
+    Error(RpcError),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    ParseError,
+    MethodNotFound,
+    InvalidParams,
+    UnsupportedVersion,
+    UnknownStrategy,
+    ToolFailed,
+    ReloadFailed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RpcError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl RpcError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for RpcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for RpcError {}
+
+/// Result of `hello`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Hello {
+    pub version: u32,
+    pub daemon: String,
+}
+
+/// Result of `use` and `reset`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StrategyChanged {
+    pub strategy: String,
+}
+
+/// Result of `tool`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ToolOutput {
+    pub status: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// Parses one request line, reporting a typed error along with whatever id
+/// could still be recovered from it.
+pub fn parse_request(line: &str) -> Result<Request, (Option<u64>, RpcError)> {
+    let value: Value = serde_json::from_str(line)
+        .map_err(|e| (None, RpcError::new(ErrorCode::ParseError, e.to_string())))?;
+    let id = value.get("id").and_then(Value::as_u64);
+
+    serde_json::from_value(value.clone()).map_err(|e| {
+        let code = match value.get("method").and_then(Value::as_str) {
+            Some(method) if Method::NAMES.contains(&method) => ErrorCode::InvalidParams,
+            _ => ErrorCode::MethodNotFound,
+        };
+        (id, RpcError::new(code, e.to_string()))
+    })
+}
+
+/// A command in the original plain text protocol, e.g. `use lazy` or
+/// `print human`.
+pub struct TextCommand {
+    pub method: Method,
+    human: bool,
+}
+
+impl TextCommand {
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+        let method = match text {
+            "print" => Method::Status,
+            "reset" => Method::Reset,
+            "pause" => Method::Pause,
+            "resume" => Method::Resume,
+            "reload" => Method::Reload,
+            _ => {
+                if let Some(format) = text.strip_prefix("print ") {
+                    return Some(Self {
+                        method: Method::Status,
+                        human: format.trim() != "json",
+                    });
+                } else if let Some(name) = text.strip_prefix("use ") {
+                    Method::Use {
+                        strategy: name.trim().to_string(),
+                    }
+                } else if let Some(args) = text.strip_prefix("tool ") {
+                    Method::Tool {
+                        args: args.split_whitespace().map(String::from).collect(),
+                    }
+                } else {
+                    return None;
+                }
+            }
+        };
+        Some(Self {
+            method,
+            human: false,
+        })
+    }
+
+    /// Formats a reply the way the text protocol always has.
+    pub fn render<T: Serialize>(&self, result: &Result<T, RpcError>) -> String {
+        let reply = match result {
+            Ok(reply) => reply,
+            Err(e) => return e.message.clone(),
+        };
+        let json = || serde_json::to_string(reply).unwrap_or_default();
+        let value = || serde_json::to_value(reply).unwrap_or_default();
+        match &self.method {
+            Method::Hello { .. } => json(),
+            Method::Use { strategy } => format!("Switched to strategy: {}", strategy),
+            Method::Status if self.human => match serde_json::from_value::<Status>(value()) {
+                Ok(status) => render_status(&status),
+                Err(_) => json(),
+            },
+            Method::Status => json(),
+            Method::Reset => format!(
+                "Strategy reset to default! Strategy in use: {}",
+                value()["strategy"].as_str().unwrap_or_default()
+            ),
+            Method::Pause => "Service paused!".to_string(),
+            Method::Resume => "Service resumed!".to_string(),
+            Method::Reload => "Config reloaded".to_string(),
+            Method::Tool { .. } => value()["stderr"].as_str().unwrap_or_default().to_string(),
+        }
+    }
+}
+
+fn render_status(status: &Status) -> String {
+    let mut msg = format!(
+        "Strategy: {}\nSpeed: {}\nActive: {}\nDegraded: {}",
+        status.strategy, status.speed, status.paused, status.degraded
+    );
+    if let Some(temperature) = status.temperature {
+        msg.push_str(&format!("\nTemperature: {:.1}°C", temperature));
+    }
+    for fan in &status.fans {
+        let rpm = fan.rpm.map_or("?".to_string(), |r| r.to_string());
+        msg.push_str(&format!(
+            "\nFan {}: {}% ({} RPM)",
+            fan.index, fan.target, rpm
+        ));
+        if let Some(raw_speed) = fan.raw_speed {
+            msg.push_str(&format!(", curve {:.1}%", raw_speed));
+        }
+    }
+    for (sensor, temperature) in &status.sensors {
+        msg.push_str(&format!("\n  {}: {:.1}°C", sensor, temperature));
+    }
+    if !status.temperature_source.is_empty() {
+        msg.push_str(&format!(
+            "\nBackend: {} / {}",
+            status.temperature_source, status.fan_actuator
+        ));
+    }
+    msg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_requests_with_and_without_params() {
+        let request =
+            parse_request(r#"{"id":3,"method":"use","params":{"strategy":"lazy"}}"#).unwrap();
+        assert_eq!(request.id, Some(3));
+        assert_eq!(
+            request.method,
+            Method::Use {
+                strategy: "lazy".to_string()
+            }
+        );
+
+        let request = parse_request(r#"{"id":4,"method":"status"}"#).unwrap();
+        assert_eq!(request.method, Method::Status);
+    }
+
+    #[test]
+    fn reports_typed_errors() {
+        let (id, e) = parse_request("{not json").unwrap_err();
+        assert_eq!((id, e.code), (None, ErrorCode::ParseError));
+
+        let (id, e) = parse_request(r#"{"id":5,"method":"explode"}"#).unwrap_err();
+        assert_eq!((id, e.code), (Some(5), ErrorCode::MethodNotFound));
+
+        let (id, e) = parse_request(r#"{"id":6,"method":"use","params":{}}"#).unwrap_err();
+        assert_eq!((id, e.code), (Some(6), ErrorCode::InvalidParams));
+    }
+
+    #[test]
+    fn responses_are_flat() {
+        let response: Response = Response {
+            id: Some(1),
+            outcome: Outcome::Error(RpcError::new(
+                ErrorCode::UnknownStrategy,
+                "Unknown strategy: x",
+            )),
+        };
+        assert_eq!(
+            serde_json::to_string(&response).unwrap(),
+            r#"{"id":1,"error":{"code":"unknown_strategy","message":"Unknown strategy: x"}}"#
+        );
+    }
+
+    #[test]
+    fn text_commands_map_to_methods() {
+        assert_eq!(TextCommand::parse("print").unwrap().method, Method::Status);
+        assert_eq!(
+            TextCommand::parse("use  quiet \n").unwrap().method,
+            Method::Use {
+                strategy: "quiet".to_string()
+            }
+        );
+        assert!(TextCommand::parse("print human").unwrap().human);
+        assert!(!TextCommand::parse("print json").unwrap().human);
+        assert!(TextCommand::parse("use").is_none());
+    }
+}
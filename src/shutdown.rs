@@ -0,0 +1,180 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, TryLockError};
+use std::thread;
+
+use log::{error, info, warn};
+
+use crate::fan_actuator::{self, FanActuator};
+use crate::fan_config::FanConfig;
+
+/// Hands the fans back to the EC and removes the sockets, however the
+/// daemon goes away: on return, on SIGTERM / SIGINT, or on a panic.
+#[derive(Clone)]
+pub struct Shutdown {
+    actuator: Arc<Mutex<Box<dyn FanActuator>>>,
+    config: Arc<Mutex<FanConfig>>,
+    sockets: Vec<PathBuf>,
+}
+
+/// Runs the shutdown when dropped, i.e. when `run_daemon` returns early.
+pub struct ShutdownGuard(Shutdown);
+
+impl Drop for ShutdownGuard {
+    fn drop(&mut self) {
+        self.0.run();
+    }
+}
+
+impl Shutdown {
+    pub fn new(
+        actuator: Arc<Mutex<Box<dyn FanActuator>>>,
+        config: Arc<Mutex<FanConfig>>,
+        sockets: Vec<PathBuf>,
+    ) -> Self {
+        Self {
+            actuator,
+            config,
+            sockets,
+        }
+    }
+
+    pub fn guard(&self) -> ShutdownGuard {
+        ShutdownGuard(self.clone())
+    }
+
+    /// Restores auto control and removes the sockets.
+    pub fn run(&self) {
+        drop(self.run_locked());
+    }
+
+    /// Runs the shutdown and exits without releasing the actuator, so the fan
+    /// thread can't take manual control again in between.
+    pub fn exit(&self, code: i32) -> ! {
+        let _actuator = self.run_locked();
+        std::process::exit(code)
+    }
+
+    /// Waits for a fan update in flight, so no duty it writes can land after
+    /// auto control is restored, and returns the actuator still locked.
+    fn run_locked(&self) -> MutexGuard<'_, Box<dyn FanActuator>> {
+        let mut actuator = self.actuator.lock().unwrap_or_else(PoisonError::into_inner);
+        self.finish(actuator.restore_auto());
+        actuator
+    }
+
+    /// Never blocks: the panicking thread may still hold the actuator, in
+    /// which case a fresh one is built from the config to restore auto
+    /// control.
+    fn run_after_panic(&self) {
+        let restored = match self.actuator.try_lock() {
+            Ok(mut actuator) => actuator.restore_auto(),
+            Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner().restore_auto(),
+            Err(TryLockError::WouldBlock) => {
+                let actuator_config = match self.config.try_lock() {
+                    Ok(config) => config.fan_actuator.clone(),
+                    Err(_) => Default::default(),
+                };
+                fan_actuator::from_config(&actuator_config).restore_auto()
+            }
+        };
+        self.finish(restored);
+    }
+
+    fn finish(&self, restored: std::io::Result<()>) {
+        match restored {
+            Ok(()) => info!("Restored automatic fan control"),
+            Err(e) => error!("Failed to restore automatic fan control: {}", e),
+        }
+
+        for socket in &self.sockets {
+            if let Err(e) = fs::remove_file(socket) {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    warn!("Failed to remove {}: {}", socket.display(), e);
+                }
+            }
+        }
+    }
+
+    pub fn install_panic_hook(&self) {
+        let shutdown = self.clone();
+        let default_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            default_hook(info);
+            shutdown.run_after_panic();
+            std::process::exit(101);
+        }));
+    }
+}
+
+/// Blocks SIGTERM, SIGINT and SIGHUP for the calling thread and every thread
+/// spawned after it, so only `spawn_signal_thread` receives them.
+pub fn block_signals() -> libc::sigset_t {
+    unsafe {
+        let mut set: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut set);
+        libc::sigaddset(&mut set, libc::SIGTERM);
+        libc::sigaddset(&mut set, libc::SIGINT);
+        libc::sigaddset(&mut set, libc::SIGHUP);
+        libc::pthread_sigmask(libc::SIG_BLOCK, &set, std::ptr::null_mut());
+        set
+    }
+}
+
+/// Waits for the signals in `set`: SIGHUP calls `reload`, anything else runs
+/// the shutdown and exits.
+pub fn spawn_signal_thread(
+    set: libc::sigset_t,
+    shutdown: Shutdown,
+    reload: impl Fn() + Send + 'static,
+) {
+    thread::spawn(move || loop {
+        let mut signal = 0;
+        if unsafe { libc::sigwait(&set, &mut signal) } != 0 {
+            continue;
+        }
+
+        if signal == libc::SIGHUP {
+            info!("SIGHUP received, reloading config");
+            reload();
+        } else {
+            info!("Signal {} received, shutting down", signal);
+            shutdown.exit(0);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fan_actuator::mock::MockActuator;
+    use crate::fan_config::default::default_fan_config;
+    use std::sync::Barrier;
+    use std::time::Duration;
+
+    #[test]
+    fn waits_for_a_fan_update_in_flight() {
+        let actuator: Arc<Mutex<Box<dyn FanActuator>>> =
+            Arc::new(Mutex::new(Box::new(MockActuator::new(100, 2))));
+        let config = Arc::new(Mutex::new(default_fan_config()));
+        let shutdown = Shutdown::new(Arc::clone(&actuator), config, vec![]);
+
+        let locked = Arc::new(Barrier::new(2));
+        let tick = {
+            let actuator = Arc::clone(&actuator);
+            let locked = Arc::clone(&locked);
+            thread::spawn(move || {
+                let mut actuator = actuator.lock().unwrap();
+                locked.wait();
+                thread::sleep(Duration::from_millis(100));
+                actuator.set_duty(None, 50).unwrap();
+            })
+        };
+        locked.wait();
+        shutdown.run();
+        tick.join().unwrap();
+
+        // restored after the duty, not on a second actuator beside it
+        assert_eq!(actuator.lock().unwrap().read_rpm().unwrap(), vec![0, 0]);
+    }
+}
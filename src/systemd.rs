@@ -0,0 +1,226 @@
+use std::os::fd::{FromRawFd, RawFd};
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram, UnixListener};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{debug, warn};
+
+const SD_LISTEN_FDS_START: RawFd = 3;
+
+fn for_this_process(var: &str) -> bool {
+    match std::env::var(var) {
+        Ok(pid) => pid.parse::<u32>().ok() == Some(std::process::id()),
+        Err(_) => true,
+    }
+}
+
+/// Listening sockets passed in by socket activation. The `LISTEN_*`
+/// variables are cleared so `framework_tool` children don't inherit them.
+pub fn listen_fds() -> Vec<UnixListener> {
+    let fds = std::env::var("LISTEN_FDS").ok();
+    let ours = std::env::var_os("LISTEN_PID").is_some() && for_this_process("LISTEN_PID");
+    for var in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+        std::env::remove_var(var);
+    }
+
+    let count = match fds.and_then(|n| n.parse::<RawFd>().ok()) {
+        Some(n) if ours => n,
+        _ => return Vec::new(),
+    };
+
+    (SD_LISTEN_FDS_START..SD_LISTEN_FDS_START + count)
+        .map(|fd| unsafe {
+            libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+            UnixListener::from_raw_fd(fd)
+        })
+        .collect()
+}
+
+/// Takes the inherited listener bound to `path`, if there is one.
+pub fn take_listener(inherited: &mut Vec<UnixListener>, path: &Path) -> Option<UnixListener> {
+    let index = inherited.iter().position(|listener| {
+        listener
+            .local_addr()
+            .ok()
+            .and_then(|addr| addr.as_pathname().map(|p| p == path))
+            .unwrap_or(false)
+    })?;
+    Some(inherited.remove(index))
+}
+
+/// Talks to the service manager over `NOTIFY_SOCKET`. Every method is a
+/// no-op when the daemon wasn't started by systemd.
+pub struct Notifier {
+    socket: Option<(UnixDatagram, SocketAddr)>,
+    watchdog: Option<Duration>,
+    ready: bool,
+}
+
+impl Notifier {
+    pub fn from_env() -> Self {
+        let socket = std::env::var_os("NOTIFY_SOCKET");
+        let watchdog = std::env::var("WATCHDOG_USEC")
+            .ok()
+            .filter(|_| for_this_process("WATCHDOG_PID"))
+            .and_then(|usec| usec.parse::<u64>().ok())
+            .filter(|usec| *usec > 0)
+            .map(Duration::from_micros);
+        for var in ["NOTIFY_SOCKET", "WATCHDOG_USEC", "WATCHDOG_PID"] {
+            std::env::remove_var(var);
+        }
+
+        let mut notifier = match socket {
+            Some(path) => Self::connect(Path::new(&path)),
+            None => Self::disabled(),
+        };
+        if notifier.socket.is_some() {
+            notifier.watchdog = watchdog;
+        }
+        notifier
+    }
+
+    pub fn disabled() -> Self {
+        Self {
+            socket: None,
+            watchdog: None,
+            ready: false,
+        }
+    }
+
+    /// `path` may start with `@` for an abstract socket, as systemd uses.
+    pub fn connect(path: &Path) -> Self {
+        let addr = match path.to_str().and_then(|p| p.strip_prefix('@')) {
+            Some(name) => SocketAddr::from_abstract_name(name),
+            None => SocketAddr::from_pathname(path),
+        };
+        let socket = addr.and_then(|addr| Ok((UnixDatagram::unbound()?, addr)));
+        match socket {
+            Ok(socket) => Self {
+                socket: Some(socket),
+                watchdog: None,
+                ready: false,
+            },
+            Err(e) => {
+                warn!("Invalid NOTIFY_SOCKET {}: {}", path.display(), e);
+                Self::disabled()
+            }
+        }
+    }
+
+    fn notify(&self, state: &str) {
+        if let Some((socket, addr)) = &self.socket {
+            debug!("sd_notify {}", state);
+            if let Err(e) = socket.send_to_addr(state.as_bytes(), addr) {
+                warn!("Failed to notify service manager: {}", e);
+            }
+        }
+    }
+
+    /// Sends `READY=1` the first time it is called.
+    pub fn ready(&mut self) {
+        if !self.ready {
+            self.ready = true;
+            self.notify("READY=1");
+        }
+    }
+
+    pub fn watchdog(&self) {
+        if self.watchdog.is_some() {
+            self.notify("WATCHDOG=1");
+        }
+    }
+
+    /// Half the watchdog timeout, as recommended by sd_watchdog_enabled(3).
+    pub fn watchdog_interval(&self) -> Option<Duration> {
+        self.watchdog.map(|timeout| timeout / 2)
+    }
+
+    /// Sleeps for `duration`, pinging the watchdog often enough that a long
+    /// update interval doesn't look like a hang.
+    pub fn sleep(&self, duration: Duration) {
+        let Some(interval) = self.watchdog_interval() else {
+            thread::sleep(duration);
+            return;
+        };
+        let deadline = Instant::now() + duration;
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return;
+            }
+            thread::sleep(left.min(interval));
+            self.watchdog();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recv(socket: &UnixDatagram) -> String {
+        let mut buf = [0u8; 64];
+        let n = socket.recv(&mut buf).unwrap();
+        String::from_utf8_lossy(&buf[..n]).to_string()
+    }
+
+    #[test]
+    fn ready_is_sent_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notify");
+        let manager = UnixDatagram::bind(&path).unwrap();
+        manager.set_nonblocking(true).unwrap();
+
+        let mut notifier = Notifier::connect(&path);
+        notifier.ready();
+        notifier.ready();
+
+        assert_eq!(recv(&manager), "READY=1");
+        assert!(manager.recv(&mut [0u8; 64]).is_err());
+    }
+
+    #[test]
+    fn watchdog_pings_while_sleeping() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notify");
+        let manager = UnixDatagram::bind(&path).unwrap();
+        manager.set_nonblocking(true).unwrap();
+
+        let mut notifier = Notifier::connect(&path);
+        notifier.watchdog = Some(Duration::from_millis(20));
+        notifier.sleep(Duration::from_millis(35));
+
+        assert_eq!(recv(&manager), "WATCHDOG=1");
+        assert_eq!(recv(&manager), "WATCHDOG=1");
+    }
+
+    #[test]
+    fn watchdog_is_silent_when_disabled() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notify");
+        let manager = UnixDatagram::bind(&path).unwrap();
+        manager.set_nonblocking(true).unwrap();
+
+        Notifier::connect(&path).watchdog();
+
+        assert!(manager.recv(&mut [0u8; 64]).is_err());
+    }
+
+    #[test]
+    fn take_listener_matches_bound_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let control = dir.path().join("control.sock");
+        let info = dir.path().join("info.sock");
+        let mut inherited = vec![
+            UnixListener::bind(&info).unwrap(),
+            UnixListener::bind(&control).unwrap(),
+        ];
+
+        assert!(take_listener(&mut inherited, &control).is_some());
+        assert!(take_listener(&mut inherited, &control).is_none());
+        assert!(take_listener(&mut inherited, &info).is_some());
+        assert!(inherited.is_empty());
+    }
+}
@@ -0,0 +1,146 @@
+use std::process::Command;
+
+use log::debug;
+
+use super::{TempReadings, TemperatureSource};
+
+#[derive(Debug)]
+pub struct TempParsed {
+    pub f75303_local: Option<u32>,
+    pub f75303_cpu: Option<u32>,
+    pub f75303_ddr: Option<u32>,
+    pub apu: Option<u32>,
+    pub dgpu_vr: Option<u32>,
+    pub dgpu_vram: Option<u32>,
+    pub dgpu_amb: Option<u32>,
+    pub dgpu_temp: Option<u32>,
+    pub fan_speeds: Vec<u32>,
+}
+
+pub fn parse_temp(input: &str) -> TempParsed {
+    let mut out = TempParsed {
+        f75303_local: None,
+        f75303_cpu: None,
+        f75303_ddr: None,
+        apu: None,
+        dgpu_vr: None,
+        dgpu_vram: None,
+        dgpu_amb: None,
+        dgpu_temp: None,
+        fan_speeds: Vec::new(),
+    };
+
+    for line in input.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let to_val = |s: &str| s.parse::<u32>().ok();
+
+        if let Some(v) = line.strip_prefix("F75303_Local:") {
+            out.f75303_local = to_val(v.split_whitespace().next().unwrap_or(""));
+        } else if let Some(v) = line.strip_prefix("F75303_CPU:") {
+            out.f75303_cpu = to_val(v.split_whitespace().next().unwrap_or(""));
+        } else if let Some(v) = line.strip_prefix("F75303_DDR:") {
+            out.f75303_ddr = to_val(v.split_whitespace().next().unwrap_or(""));
+        } else if let Some(v) = line.strip_prefix("APU:") {
+            out.apu = to_val(v.split_whitespace().next().unwrap_or(""));
+        } else if let Some(v) = line.strip_prefix("dGPU VR:") {
+            out.dgpu_vr = to_val(v.split_whitespace().next().unwrap_or(""));
+        } else if let Some(v) = line.strip_prefix("dGPU VRAM:") {
+            out.dgpu_vram = to_val(v.split_whitespace().next().unwrap_or(""));
+        } else if let Some(v) = line.strip_prefix("dGPU AMB:") {
+            out.dgpu_amb = to_val(v.split_whitespace().next().unwrap_or(""));
+        } else if let Some(v) = line.strip_prefix("dGPU temp:") {
+            // `NotPowered` is no reading, not 0 °C, or a max aggregate would
+            // ignore that the APU line is missing
+            out.dgpu_temp = to_val(v.split_whitespace().next().unwrap_or(""));
+        } else if let Some(v) = line.strip_prefix("Fan Speed:") {
+            if let Some(num) = to_val(v.split_whitespace().next().unwrap_or("")) {
+                out.fan_speeds.push(num);
+            }
+        }
+    }
+
+    out
+}
+
+impl From<TempParsed> for TempReadings {
+    fn from(parsed: TempParsed) -> Self {
+        let mut readings = TempReadings {
+            fan_speeds: parsed.fan_speeds,
+            ..Default::default()
+        };
+
+        let named = [
+            ("F75303_Local", parsed.f75303_local),
+            ("F75303_CPU", parsed.f75303_cpu),
+            ("F75303_DDR", parsed.f75303_ddr),
+            ("APU", parsed.apu),
+            ("dGPU_VR", parsed.dgpu_vr),
+            ("dGPU_VRAM", parsed.dgpu_vram),
+            ("dGPU_AMB", parsed.dgpu_amb),
+            ("dGPU_temp", parsed.dgpu_temp),
+        ];
+        for (name, value) in named {
+            if let Some(v) = value {
+                readings.sensors.insert(name.to_string(), v as f32);
+            }
+        }
+
+        readings
+    }
+}
+
+/// Reads the EC sensors by scraping `framework_tool --thermal`.
+pub struct FrameworkToolSource;
+
+impl TemperatureSource for FrameworkToolSource {
+    fn name(&self) -> &'static str {
+        "framework_tool"
+    }
+
+    fn read(&mut self) -> std::io::Result<TempReadings> {
+        let output = Command::new("framework_tool").arg("--thermal").output()?;
+        if !output.status.success() {
+            return Err(std::io::Error::other(format!(
+                "framework_tool --thermal failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let parsed = parse_temp(&stdout);
+        debug!("{:?}", parsed);
+        Ok(parsed.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fan_config::Strategy;
+
+    #[test]
+    fn unpowered_dgpu_is_not_a_reading() {
+        let readings: TempReadings = parse_temp(
+            "  F75303_Local: 40 C\n  APU:          62 C\n  dGPU temp:    NotPowered\n  Fan Speed:    1200 RPM\n",
+        )
+        .into();
+        assert_eq!(readings.get("APU"), Some(62.0));
+        assert_eq!(readings.get("dGPU_temp"), None);
+        assert_eq!(readings.fan_speeds, vec![1200]);
+    }
+
+    #[test]
+    fn missing_apu_fails_the_update() {
+        let readings: TempReadings =
+            parse_temp("  F75303_Local: 40 C\n  dGPU temp:    NotPowered\n").into();
+        assert!(readings.check(&Strategy::default()).is_err());
+
+        let readings: TempReadings =
+            parse_temp("  F75303_Local: 40 C\n  dGPU temp:    55 C\n").into();
+        assert!(readings.check(&Strategy::default()).is_ok());
+        assert_eq!(readings.get("dGPU_temp"), Some(55.0));
+    }
+}
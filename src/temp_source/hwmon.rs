@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::debug;
+
+use super::{TempReadings, TemperatureSource};
+
+/// Reads `<root>/hwmon*/temp*_input`.
+///
+/// Every sensor is reported as `"<chip name> <label>"` (e.g. `k10temp Tctl`,
+/// `amdgpu edge`), falling back to `tempN` when the driver exposes no label.
+/// `aliases` additionally maps a friendly name such as `APU` to one of those keys.
+pub struct HwmonSource {
+    root: PathBuf,
+    aliases: HashMap<String, String>,
+}
+
+impl HwmonSource {
+    pub fn new(root: &str, aliases: &HashMap<String, String>) -> Self {
+        Self {
+            root: PathBuf::from(root),
+            aliases: aliases.clone(),
+        }
+    }
+}
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    fs::read_to_string(path).ok().map(|s| s.trim().to_string())
+}
+
+/// Splits `temp10_input` into `("temp", 10, "_input")`, so that `temp2`
+/// sorts before `temp10` and `hwmon2` before `hwmon10`.
+fn numeric_key(path: &Path) -> (String, u64, String) {
+    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+    let start = name
+        .find(|c: char| c.is_ascii_digit())
+        .unwrap_or(name.len());
+    let end = name[start..]
+        .find(|c: char| !c.is_ascii_digit())
+        .map_or(name.len(), |i| start + i);
+    (
+        name[..start].to_string(),
+        name[start..end].parse().unwrap_or(0),
+        name[end..].to_string(),
+    )
+}
+
+fn sorted_entries(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut entries = fs::read_dir(dir)?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .collect::<Vec<_>>();
+    entries.sort_by_cached_key(|path| numeric_key(path));
+    Ok(entries)
+}
+
+fn read_chip(chip: &Path, readings: &mut TempReadings) -> std::io::Result<()> {
+    let chip_name = read_trimmed(&chip.join("name")).unwrap_or_else(|| "hwmon".to_string());
+
+    for path in sorted_entries(chip)? {
+        let Some(file) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+
+        if let Some(channel) = file
+            .strip_prefix("temp")
+            .and_then(|f| f.strip_suffix("_input"))
+        {
+            let Some(millideg) = read_trimmed(&path).and_then(|v| v.parse::<i64>().ok()) else {
+                debug!("Skipping unreadable {}", path.display());
+                continue;
+            };
+            let label = read_trimmed(&chip.join(format!("temp{}_label", channel)))
+                .unwrap_or_else(|| format!("temp{}", channel));
+            readings
+                .sensors
+                .insert(format!("{} {}", chip_name, label), millideg as f32 / 1000.0);
+        } else if file.starts_with("fan") && file.ends_with("_input") {
+            if let Some(rpm) = read_trimmed(&path).and_then(|v| v.parse::<u32>().ok()) {
+                readings.fan_speeds.push(rpm);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+impl TemperatureSource for HwmonSource {
+    fn name(&self) -> &'static str {
+        "hwmon"
+    }
+
+    fn read(&mut self) -> std::io::Result<TempReadings> {
+        let mut readings = TempReadings::default();
+
+        for chip in sorted_entries(&self.root)? {
+            let is_hwmon = chip
+                .file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.starts_with("hwmon"));
+            if !is_hwmon {
+                continue;
+            }
+            if let Err(e) = read_chip(&chip, &mut readings) {
+                debug!("Skipping {}: {}", chip.display(), e);
+            }
+        }
+
+        for (alias, key) in &self.aliases {
+            if let Some(v) = readings.get(key) {
+                readings.sensors.insert(alias.clone(), v);
+            }
+        }
+
+        Ok(readings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn add_chip(root: &Path, dir: &str, name: &str, temps: &[(u32, Option<&str>, i64)]) {
+        let chip = root.join(dir);
+        fs::create_dir(&chip).unwrap();
+        fs::write(chip.join("name"), format!("{}\n", name)).unwrap();
+        for (channel, label, millideg) in temps {
+            fs::write(
+                chip.join(format!("temp{}_input", channel)),
+                format!("{}\n", millideg),
+            )
+            .unwrap();
+            if let Some(label) = label {
+                fs::write(chip.join(format!("temp{}_label", channel)), label).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn reads_labelled_sensors_and_aliases() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        add_chip(root, "hwmon2", "k10temp", &[(1, Some("Tctl"), 61_250)]);
+        add_chip(
+            root,
+            "hwmon10",
+            "amdgpu",
+            &[(1, Some("edge"), 48_000), (2, None, 50_000)],
+        );
+        fs::write(root.join("hwmon10/fan1_input"), "2100\n").unwrap();
+
+        let aliases = [("APU".to_string(), "k10temp Tctl".to_string())].into();
+        let readings = HwmonSource::new(root.to_str().unwrap(), &aliases)
+            .read()
+            .unwrap();
+
+        assert_eq!(readings.get("k10temp Tctl"), Some(61.25));
+        assert_eq!(readings.get("amdgpu edge"), Some(48.0));
+        assert_eq!(readings.get("amdgpu temp2"), Some(50.0));
+        assert_eq!(readings.get("APU"), Some(61.25));
+        assert_eq!(readings.fan_speeds, vec![2100]);
+    }
+
+    #[test]
+    fn entries_sort_by_number() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        for dir in ["hwmon10", "hwmon2", "hwmon1"] {
+            fs::create_dir(root.join(dir)).unwrap();
+        }
+        let names: Vec<_> = sorted_entries(root)
+            .unwrap()
+            .iter()
+            .map(|p| p.file_name().unwrap().to_str().unwrap().to_string())
+            .collect();
+        assert_eq!(names, vec!["hwmon1", "hwmon2", "hwmon10"]);
+
+        for fan in [10, 2, 1] {
+            fs::write(
+                root.join(format!("hwmon1/fan{}_input", fan)),
+                fan.to_string(),
+            )
+            .unwrap();
+        }
+        let readings = HwmonSource::new(root.to_str().unwrap(), &HashMap::new())
+            .read()
+            .unwrap();
+        assert_eq!(readings.fan_speeds, vec![1, 2, 10]);
+    }
+
+    #[test]
+    fn skips_unreadable_chips_and_inputs() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        add_chip(root, "hwmon0", "k10temp", &[(1, Some("Tctl"), 55_000)]);
+        fs::create_dir(root.join("hwmon0/temp2_input")).unwrap();
+        std::os::unix::fs::symlink(root.join("missing"), root.join("hwmon1")).unwrap();
+
+        let readings = HwmonSource::new(root.to_str().unwrap(), &HashMap::new())
+            .read()
+            .unwrap();
+
+        assert_eq!(readings.sensors.len(), 1);
+        assert_eq!(readings.get("k10temp Tctl"), Some(55.0));
+    }
+}
@@ -0,0 +1,22 @@
+use super::{TempReadings, TemperatureSource};
+
+/// Returns the same fixed readings on every tick, for rigs without real sensors.
+pub struct MockSource {
+    readings: TempReadings,
+}
+
+impl MockSource {
+    pub fn new(readings: TempReadings) -> Self {
+        Self { readings }
+    }
+}
+
+impl TemperatureSource for MockSource {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn read(&mut self) -> std::io::Result<TempReadings> {
+        Ok(self.readings.clone())
+    }
+}
@@ -0,0 +1,94 @@
+use std::collections::BTreeMap;
+
+use crate::fan_config::{Aggregation, SensorInput, Strategy, TemperatureSourceConfig};
+
+pub mod framework_tool;
+pub mod hwmon;
+pub mod mock;
+pub mod thermal_zone;
+
+/// One snapshot of every sensor a backend knows about, keyed by sensor name.
+#[derive(Debug, Clone, Default)]
+pub struct TempReadings {
+    pub sensors: BTreeMap<String, f32>,
+    pub fan_speeds: Vec<u32>,
+}
+
+impl TempReadings {
+    pub fn get(&self, sensor: &str) -> Option<f32> {
+        self.sensors.get(sensor).copied()
+    }
+
+    /// Fails when a required sensor of `strategy` is missing, or when none of
+    /// its sensors were read at all.
+    pub fn check(&self, strategy: &Strategy) -> std::io::Result<()> {
+        let missing = |name: &str| self.get(name).is_none();
+        if let Some(input) = strategy
+            .sensors
+            .iter()
+            .find(|input| input.required && missing(&input.name))
+        {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("required sensor {} is missing", input.name),
+            ));
+        }
+        if strategy.sensor_names().all(missing) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "none of the strategy's sensors were read",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Combines the offset readings of `inputs`, skipping sensors that are
+    /// missing. Returns `None` when none of them were read.
+    pub fn aggregate(&self, inputs: &[SensorInput], aggregation: &Aggregation) -> Option<f32> {
+        let values: Vec<(f32, f32)> = inputs
+            .iter()
+            .filter_map(|input| {
+                self.get(&input.name)
+                    .map(|v| (v + input.offset, input.weight))
+            })
+            .collect();
+        if values.is_empty() {
+            return None;
+        }
+
+        match aggregation {
+            Aggregation::Max => values.iter().map(|(v, _)| *v).reduce(f32::max),
+            Aggregation::WeightedAverage => {
+                let total_weight: f32 = values.iter().map(|(_, w)| w).sum();
+                if total_weight <= 0.0 {
+                    return None;
+                }
+                Some(values.iter().map(|(v, w)| v * w).sum::<f32>() / total_weight)
+            }
+        }
+    }
+}
+
+pub trait TemperatureSource: Send {
+    fn name(&self) -> &'static str;
+    fn read(&mut self) -> std::io::Result<TempReadings>;
+}
+
+pub fn from_config(config: &TemperatureSourceConfig) -> Box<dyn TemperatureSource> {
+    match config {
+        TemperatureSourceConfig::FrameworkTool => Box::new(framework_tool::FrameworkToolSource),
+        TemperatureSourceConfig::Hwmon { root, sensors } => {
+            Box::new(hwmon::HwmonSource::new(root, sensors))
+        }
+        TemperatureSourceConfig::ThermalZone { root } => {
+            Box::new(thermal_zone::ThermalZoneSource::new(root))
+        }
+        TemperatureSourceConfig::Mock {
+            sensors,
+            fan_speeds,
+        } => Box::new(mock::MockSource::new(TempReadings {
+            sensors: sensors.iter().map(|(k, v)| (k.clone(), *v)).collect(),
+            fan_speeds: fan_speeds.clone(),
+        })),
+    }
+}
@@ -0,0 +1,52 @@
+use std::fs;
+use std::path::PathBuf;
+
+use super::{TempReadings, TemperatureSource};
+
+/// Reads `<root>/thermal_zone*/temp`, naming each sensor after the zone's `type`.
+pub struct ThermalZoneSource {
+    root: PathBuf,
+}
+
+impl ThermalZoneSource {
+    pub fn new(root: &str) -> Self {
+        Self {
+            root: PathBuf::from(root),
+        }
+    }
+}
+
+impl TemperatureSource for ThermalZoneSource {
+    fn name(&self) -> &'static str {
+        "thermal_zone"
+    }
+
+    fn read(&mut self) -> std::io::Result<TempReadings> {
+        let mut readings = TempReadings::default();
+
+        for entry in fs::read_dir(&self.root)? {
+            let path = entry?.path();
+            let is_zone = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.starts_with("thermal_zone"));
+            if !is_zone {
+                continue;
+            }
+
+            let Ok(kind) = fs::read_to_string(path.join("type")) else {
+                continue;
+            };
+            let Ok(raw) = fs::read_to_string(path.join("temp")) else {
+                continue;
+            };
+            if let Ok(millideg) = raw.trim().parse::<i64>() {
+                readings
+                    .sensors
+                    .insert(kind.trim().to_string(), millideg as f32 / 1000.0);
+            }
+        }
+
+        Ok(readings)
+    }
+}